@@ -0,0 +1,28 @@
+-- Initial PostgreSQL schema, equivalent to all SQLite migrations up to this point.
+create table links
+(
+    id                    TEXT   not null
+        constraint links_pk
+            primary key,
+    redirect_to           TEXT   not null,
+    max_uses              BIGINT not null,
+    invocations           BIGINT not null,
+    created_at            BIGINT not null,
+    valid_for             BIGINT not null,
+    management_token_hash TEXT
+);
+
+-- Every redirect served for a link, used for the per-link statistics.
+create table visits
+(
+    link_id           TEXT   not null
+        constraint visits_links_id_fk
+            references links (id)
+            on delete cascade,
+    visited_at        BIGINT not null,
+    referrer          TEXT,
+    user_agent_family TEXT,
+    country           TEXT
+);
+
+CREATE INDEX visits_link_id_visited_at_idx ON visits(link_id, visited_at);
@@ -0,0 +1,3 @@
+-- HTTP status code the link redirects with.
+-- Links created before this migration keep redirecting with 307, like they always did.
+ALTER TABLE links ADD COLUMN redirect_type BIGINT not null default 307;
@@ -0,0 +1,2 @@
+-- Speeds up finding existing links to a target, used to deduplicate anonymous links.
+CREATE INDEX links_redirect_to_idx ON links(redirect_to);
@@ -0,0 +1,3 @@
+-- Marks the links that are handed out to everyone who shortens the same target.
+-- Older links without a management token weren't necessarily created that way, so they aren't shared.
+ALTER TABLE links ADD COLUMN shared BOOLEAN not null default false;
@@ -0,0 +1,3 @@
+-- Hash of the secret token a link creator can use to edit or delete their link.
+-- Links created before this migration don't have a token and can't be managed.
+ALTER TABLE links ADD COLUMN management_token_hash TEXT;
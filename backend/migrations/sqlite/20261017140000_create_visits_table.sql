@@ -0,0 +1,14 @@
+-- Every redirect served for a link, used for the per-link statistics.
+create table visits
+(
+    link_id           TEXT    not null
+        constraint visits_links_id_fk
+            references links (id)
+            on delete cascade,
+    visited_at        integer not null,
+    referrer          TEXT,
+    user_agent_family TEXT,
+    country           TEXT
+);
+
+CREATE INDEX visits_link_id_visited_at_idx ON visits(link_id, visited_at);
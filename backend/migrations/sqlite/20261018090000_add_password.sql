@@ -0,0 +1,3 @@
+-- Argon2 hash of the password that has to be entered before the link redirects.
+-- Links without a password redirect right away.
+ALTER TABLE links ADD COLUMN password_hash TEXT;
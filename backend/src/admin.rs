@@ -0,0 +1,330 @@
+use std::future::{Ready, ready};
+
+use actix_web::{delete, dev::Payload, FromRequest, get, HttpRequest, HttpResponse, post, Responder, web};
+use actix_web::http::header::CONTENT_DISPOSITION;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::CONFIG;
+use crate::error::ShortyError;
+use crate::link::{LinkResponse, LinkStore};
+use crate::transfer::{ConflictStrategy, Format, RecordDecoder};
+use crate::util::{bearer_token, digest_token};
+
+/// How many links a page of the link list contains, if not specified otherwise.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+/// How many links a page of the link list may contain at most.
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Extractor that only succeeds if the request carries one of the configured `admin_tokens`.
+/// Adding it to the parameters of an endpoint restricts that endpoint to operators.
+pub struct Admin;
+
+impl FromRequest for Admin {
+	type Error = ShortyError;
+	type Future = Ready<Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		let Some(token) = bearer_token(req) else {
+			return ready(Err(ShortyError::AdminTokenMissing));
+		};
+
+		let token_digest = digest_token(token);
+		let is_admin = CONFIG.load().admin_tokens
+			.iter()
+			.any(|admin_token| digest_token(admin_token) == token_digest);
+
+		if is_admin {
+			ready(Ok(Admin))
+		} else {
+			warn!("Rejected admin request with an invalid token to {}", req.path());
+			ready(Err(ShortyError::InvalidAdminToken))
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LinkListQuery {
+	/// Only list links whose ID or target contain this term.
+	search: Option<String>,
+	/// The page to retrieve, starting at 0.
+	#[serde(default)]
+	page: i64,
+	/// How many links a page contains, defaults to 50 and can be at most 500.
+	per_page: Option<i64>,
+}
+
+/// A page of links.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LinkList {
+	pub links: Vec<LinkResponse>,
+	pub page: i64,
+	pub per_page: i64,
+	/// The total amount of links matching the search.
+	pub total: i64,
+}
+
+/// The domain whose links should be purged.
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({"domain": "phishing.example"}))]
+pub struct PurgeRequest {
+	/// Links to this domain and all of its subdomains get deleted.
+	pub domain: String,
+}
+
+/// The result of a purge.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PurgeResponse {
+	/// The IDs of the deleted links.
+	pub deleted: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+	/// The format of the export, defaults to JSON Lines.
+	#[serde(default)]
+	format: Format,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+	/// The format of the request body, defaults to JSON Lines.
+	#[serde(default)]
+	format: Format,
+	/// What happens to links whose ID is already used, defaults to skipping them.
+	#[serde(default)]
+	conflict: ConflictStrategy,
+}
+
+/// List links
+///
+/// Lists all links, including expired ones that weren't cleaned up yet, newest first.
+#[utoipa::path(
+	tag = "/admin",
+	context_path = "/admin",
+	params(LinkListQuery),
+	responses(
+		(status = 200, body = LinkList, description = "A page of links"),
+		(status = 401, body = ErrorResponse, description = "No admin token was provided"),
+		(status = 403, body = ErrorResponse, description = "The admin token is invalid"),
+	),
+	security(("admin_token" = [])),
+)]
+#[get("/links")]
+async fn list_links(
+	_: Admin,
+	query: web::Query<LinkListQuery>,
+	link_store: web::Data<LinkStore>,
+) -> Result<impl Responder, ShortyError> {
+	let query = query.into_inner();
+	let page = query.page.max(0);
+	let per_page = query.per_page
+		.unwrap_or(DEFAULT_PAGE_SIZE)
+		.clamp(1, MAX_PAGE_SIZE);
+
+	let (links, total) = link_store
+		.list(query.search.as_deref(), per_page, page.saturating_mul(per_page))
+		.await?;
+
+
+	Ok(
+		HttpResponse::Ok().json(LinkList {
+			links: links.iter().map(LinkResponse::from).collect(),
+			page,
+			per_page,
+			total,
+		})
+	)
+}
+
+/// Expire a link
+///
+/// Expires a link immediately, it gets removed on the next clean up.
+#[utoipa::path(
+	tag = "/admin",
+	context_path = "/admin",
+	params((
+		"link_id" = inline(String),
+		Path,
+		description = "The id of the link to expire",
+	)),
+	responses(
+		(status = 200, body = LinkResponse, description = "The link was expired"),
+		(status = 401, body = ErrorResponse, description = "No admin token was provided"),
+		(status = 403, body = ErrorResponse, description = "The admin token is invalid"),
+		(status = 404, body = ErrorResponse, description = "Shortened ID couldn't be found"),
+	),
+	security(("admin_token" = [])),
+)]
+#[post("/links/{link_id}/expire")]
+async fn expire_link(
+	_: Admin,
+	params: web::Path<String>,
+	link_store: web::Data<LinkStore>,
+) -> Result<impl Responder, ShortyError> {
+	let link_id = params.into_inner();
+
+	let link = link_store.force_expire(link_id.as_str()).await?;
+	info!("Admin expired {link_id}");
+
+
+	Ok(HttpResponse::Ok().json(LinkResponse::from(&link)))
+}
+
+/// Delete a link
+#[utoipa::path(
+	tag = "/admin",
+	context_path = "/admin",
+	params((
+		"link_id" = inline(String),
+		Path,
+		description = "The id of the link to delete",
+	)),
+	responses(
+		(status = 204, description = "The link was deleted"),
+		(status = 401, body = ErrorResponse, description = "No admin token was provided"),
+		(status = 403, body = ErrorResponse, description = "The admin token is invalid"),
+		(status = 404, body = ErrorResponse, description = "Shortened ID couldn't be found"),
+	),
+	security(("admin_token" = [])),
+)]
+#[delete("/links/{link_id}")]
+async fn delete_link(
+	_: Admin,
+	params: web::Path<String>,
+	link_store: web::Data<LinkStore>,
+) -> Result<impl Responder, ShortyError> {
+	let link_id = params.into_inner();
+
+	link_store.force_delete(link_id.as_str()).await?;
+	info!("Admin deleted {link_id}");
+
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Purge a domain
+///
+/// Deletes all links that redirect to a domain or any of its subdomains.
+#[utoipa::path(
+	tag = "/admin",
+	context_path = "/admin",
+	request_body(content = PurgeRequest, description = "The domain to purge"),
+	responses(
+		(status = 200, body = PurgeResponse, description = "The links to the domain were deleted"),
+		(status = 400, body = ErrorResponse, description = "Json is malformed"),
+		(status = 401, body = ErrorResponse, description = "No admin token was provided"),
+		(status = 403, body = ErrorResponse, description = "The admin token is invalid"),
+	),
+	security(("admin_token" = [])),
+)]
+#[post("/purge")]
+async fn purge_domain(
+	_: Admin,
+	purge: web::Json<PurgeRequest>,
+	link_store: web::Data<LinkStore>,
+) -> Result<impl Responder, ShortyError> {
+	let domain = purge.into_inner().domain;
+
+	let deleted = link_store.purge_domain(domain.as_str()).await?;
+	info!("Admin purged {} links to {domain}", deleted.len());
+
+
+	Ok(HttpResponse::Ok().json(PurgeResponse { deleted }))
+}
+
+/// Export links
+///
+/// Exports all links, including expired ones that weren't cleaned up yet, as JSON Lines or CSV.
+/// Every link keeps its creation time, invocations, expiry, password and management token.
+#[utoipa::path(
+	tag = "/admin",
+	context_path = "/admin",
+	params(ExportQuery),
+	responses(
+		(status = 200, content_type = "application/x-ndjson", description = "All links, one per line"),
+		(status = 400, body = ErrorResponse, description = "The format is unknown"),
+		(status = 401, body = ErrorResponse, description = "No admin token was provided"),
+		(status = 403, body = ErrorResponse, description = "The admin token is invalid"),
+	),
+	security(("admin_token" = [])),
+)]
+#[get("/export")]
+async fn export_links(
+	_: Admin,
+	query: web::Query<ExportQuery>,
+	link_store: web::Data<LinkStore>,
+) -> impl Responder {
+	let format = query.format;
+	info!("Admin exported all links");
+
+
+	HttpResponse::Ok()
+		.content_type(format.content_type())
+		.insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"shorty-links.{}\"", format.extension())))
+		.streaming(link_store.export(format))
+}
+
+/// Import links
+///
+/// Imports links from an export, the body is read and inserted record by record.
+/// Invalid records don't stop the import, they are listed in the response instead.
+#[utoipa::path(
+	tag = "/admin",
+	context_path = "/admin",
+	params(ImportQuery),
+	request_body(content = String, content_type = "application/x-ndjson", description = "Links in the format of an export"),
+	responses(
+		(status = 200, body = ImportSummary, description = "The links were imported"),
+		(status = 400, body = ErrorResponse, description = "The format or conflict strategy is unknown or the body couldn't be read"),
+		(status = 401, body = ErrorResponse, description = "No admin token was provided"),
+		(status = 403, body = ErrorResponse, description = "The admin token is invalid"),
+	),
+	security(("admin_token" = [])),
+)]
+#[post("/import")]
+async fn import_links(
+	_: Admin,
+	query: web::Query<ImportQuery>,
+	mut body: web::Payload,
+	link_store: web::Data<LinkStore>,
+) -> Result<impl Responder, ShortyError> {
+	let mut decoder = RecordDecoder::new(query.format);
+	let mut importer = link_store.importer(query.conflict);
+
+	while let Some(chunk) = body.next().await {
+		let chunk = chunk.map_err(|why| ShortyError::InvalidImport(why.to_string()))?;
+
+		for record in decoder.push(&chunk) {
+			importer.import(record).await?;
+		}
+	}
+
+	if let Some(record) = decoder.finish() {
+		importer.import(record).await?;
+	}
+
+	let summary = importer.finish();
+	info!(
+		"Admin imported {} links, overwrote {}, skipped {}, renamed {} and failed {}",
+		summary.imported, summary.overwritten, summary.skipped, summary.renamed.len(), summary.failed.len(),
+	);
+
+
+	Ok(HttpResponse::Ok().json(summary))
+}
+
+/// All endpoints of the admin API, to be mounted under `/admin`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+	cfg.service(list_links)
+		.service(expire_link)
+		.service(delete_link)
+		.service(purge_domain)
+		.service(export_links)
+		.service(import_links);
+}
@@ -0,0 +1,377 @@
+use std::path::PathBuf;
+use std::pin::pin;
+
+use chrono::{Local, TimeZone};
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{CONFIG, domain_filter, storage};
+use crate::config::{Config, config_file, ConfigSources, parse_value, SAMPLE_CONFIG};
+use crate::domain_filter::DOMAIN_FILTER;
+use crate::error::ShortyError;
+use crate::link::{Link, LinkConfig, LinkStore, RedirectType};
+use crate::tls::CertificateResolver;
+use crate::transfer::{ConflictStrategy, Format, RecordDecoder};
+
+/// How many bytes of the input are read at once while importing.
+const IMPORT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Small link shortener
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+	#[command(flatten)]
+	pub config: ConfigArgs,
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+/// Config keys set on the command line, they override the config file and the environment variables.
+#[derive(Debug, clap::Args)]
+pub struct ConfigArgs {
+	/// The config file, defaults to `SHORTY_CONFIG` or `config.toml` in the current directory.
+	#[arg(long, global = true)]
+	config: Option<PathBuf>,
+	/// The public URL that gets used for shortened links.
+	#[arg(long, global = true)]
+	public_url: Option<String>,
+	/// The path of a SQLite database or the URL of a PostgreSQL database.
+	#[arg(long, global = true)]
+	database_location: Option<String>,
+	/// The address the server binds to.
+	#[arg(long, global = true)]
+	listen_url: Option<String>,
+	/// The port the server binds to.
+	#[arg(long, global = true)]
+	port: Option<u16>,
+	/// Sets any other config key, the value is read as TOML, like `--set max_link_length=1000`.
+	#[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override, global = true)]
+	overrides: Vec<(String, toml::Value)>,
+}
+
+impl ConfigArgs {
+	#[must_use]
+	pub fn into_sources(self) -> ConfigSources {
+		let flags = [
+			("public_url", self.public_url.map(toml::Value::String)),
+			("database_location", self.database_location.map(toml::Value::String)),
+			("listen_url", self.listen_url.map(toml::Value::String)),
+			("port", self.port.map(|port| toml::Value::Integer(i64::from(port)))),
+		];
+
+		let mut overrides = self.overrides;
+		overrides.extend(
+			flags.into_iter()
+				.filter_map(|(key, value)| value.map(|value| (key.to_owned(), value)))
+		);
+
+
+		ConfigSources { file: self.config, overrides }
+	}
+}
+
+/// The commands other than `serve` work on the configured database directly, so they also work while the
+/// server is running.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+	/// Runs the server, which is also done if no command is given.
+	Serve,
+	/// Shortens a link and prints the shortened link.
+	Create {
+		/// The link that should be shortened.
+		link: String,
+		/// Custom ID for the link, a random one is generated if it isn't given.
+		#[arg(long)]
+		id: Option<String>,
+		/// How often the link may be used, 0 means infinitely.
+		#[arg(long)]
+		max_uses: Option<i64>,
+		/// How long the link is valid for in milliseconds, 0 means forever.
+		#[arg(long)]
+		valid_for: Option<i64>,
+		/// The HTTP status code the link redirects with, one of 301, 302, 303, 307 or 308.
+		#[arg(long, value_parser = parse_redirect_type)]
+		redirect_type: Option<RedirectType>,
+		/// Password that has to be entered before the link redirects.
+		#[arg(long)]
+		password: Option<String>,
+	},
+	/// Lists links, including expired ones that weren't cleaned up yet, newest first.
+	List {
+		/// Only list links whose ID or target contain this term.
+		#[arg(long)]
+		search: Option<String>,
+		/// The page to list, starting at 0.
+		#[arg(long, default_value_t = 0)]
+		page: i64,
+		/// How many links a page contains.
+		#[arg(long, default_value_t = 50)]
+		per_page: i64,
+	},
+	/// Deletes a link along with its visits.
+	Delete {
+		/// The ID of the link to delete.
+		id: String,
+	},
+	/// Deletes all expired links, like the server does every hour.
+	Clean,
+	/// Brings the database schema up to date, which the other commands and the server also do.
+	Migrate,
+	/// Checks that the config can be loaded, along with the domain lists and the certificate it points to.
+	CheckConfig,
+	/// Writes a sample config file with all keys explained to the config location.
+	InitConfig {
+		/// Replace the config file if it exists already.
+		#[arg(long)]
+		force: bool,
+	},
+	/// Writes all links to a file, including expired ones that weren't cleaned up yet.
+	Export {
+		#[arg(long, value_enum, default_value_t)]
+		format: Format,
+		/// The file to write to, the links are written to stdout if it isn't given.
+		#[arg(long, short)]
+		output: Option<PathBuf>,
+	},
+	/// Reads links from a file that was created by an export.
+	Import {
+		#[arg(long, value_enum, default_value_t)]
+		format: Format,
+		/// What happens to links whose ID is already used.
+		#[arg(long, value_enum, default_value_t)]
+		conflict: ConflictStrategy,
+		/// The file to read from, the links are read from stdin if it isn't given.
+		input: Option<PathBuf>,
+	},
+}
+
+/// Runs a command other than `serve`.
+///
+/// # Errors
+///
+/// Errors if the command fails.
+///
+/// # Panics
+///
+/// Panics if it is called with [`Command::Serve`].
+pub async fn run(command: Command) -> Result<(), ShortyError> {
+	// These mustn't load the config through `CONFIG`, which exits if it is invalid.
+	match command {
+		Command::CheckConfig => return check_config(),
+		Command::InitConfig { force } => return init_config(force),
+		_ => {},
+	}
+
+	// Created and imported links have to pass the domain lists.
+	DOMAIN_FILTER.reload()?;
+
+	let storage = storage::connect(CONFIG.load().database_location.as_str()).await?;
+	storage.migrate().await?;
+
+	let link_store = LinkStore::new(storage.clone());
+
+	let result = match command {
+		Command::Serve => unreachable!("The server isn't run as a command."),
+		Command::CheckConfig | Command::InitConfig { .. } => unreachable!("The config is handled without a database."),
+		Command::Create { link, id, max_uses, valid_for, redirect_type, password } => {
+			let link_config = LinkConfig::new(link, id, max_uses, valid_for, redirect_type, password);
+			create(&link_store, link_config).await
+		},
+		Command::List { search, page, per_page } => list(&link_store, search.as_deref(), page, per_page).await,
+		Command::Delete { id } => {
+			link_store.force_delete(id.as_str()).await
+				.map(|()| println!("Deleted {id}."))
+		},
+		Command::Clean => {
+			link_store.clean().await
+				.map(|removed| println!("Deleted {removed} expired links."))
+		},
+		Command::Migrate => {
+			println!("The database schema is up to date.");
+			Ok(())
+		},
+		Command::Export { format, output } => export(&link_store, format, output).await,
+		Command::Import { format, conflict, input } => import(&link_store, format, conflict, input).await,
+	};
+
+	storage.close().await;
+
+
+	result
+}
+
+fn parse_override(value: &str) -> Result<(String, toml::Value), String> {
+	let (key, value) = value.split_once('=')
+		.ok_or_else(|| format!("'{value}' isn't of the form KEY=VALUE"))?;
+
+
+	Ok((key.trim().to_owned(), parse_value(value.trim())))
+}
+
+fn parse_redirect_type(value: &str) -> Result<RedirectType, String> {
+	let status = value.parse::<u16>().map_err(|why| why.to_string())?;
+
+
+	RedirectType::try_from(status)
+}
+
+/// Loads the config and everything it points to.
+fn check_config() -> Result<(), ShortyError> {
+	Config::load()?;
+
+	domain_filter::check_lists()?;
+	CertificateResolver::from_config()?;
+
+	println!("The config is valid.");
+
+
+	Ok(())
+}
+
+/// Writes the sample config to the config location.
+fn init_config(force: bool) -> Result<(), ShortyError> {
+	let (file, _) = config_file();
+
+	if file.exists() && !force {
+		let message = format!("{} exists already, use --force to replace it", file.display());
+		return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, message).into());
+	}
+
+	std::fs::write(&file, SAMPLE_CONFIG)?;
+	println!("Wrote a sample config to {}.", file.display());
+
+
+	Ok(())
+}
+
+/// Creates the link and prints the shortened link, along with its management token.
+async fn create(link_store: &LinkStore, link_config: LinkConfig) -> Result<(), ShortyError> {
+	let created = link_store.create_link_with_config(link_config).await?;
+
+	println!("{}", created.link.formatted());
+	if let Some(token) = created.management_token {
+		println!("Management token: {token}");
+	}
+
+
+	Ok(())
+}
+
+/// Prints a page of links as a table.
+async fn list(link_store: &LinkStore, search: Option<&str>, page: i64, per_page: i64) -> Result<(), ShortyError> {
+	let page = page.max(0);
+	let per_page = per_page.max(1);
+
+	let (links, total) = link_store.list(search, per_page, page.saturating_mul(per_page)).await?;
+
+	let id_width = links.iter()
+		.map(|link| link.id.chars().count())
+		.max()
+		.unwrap_or_default()
+		.max("ID".len());
+
+	println!("{:<id_width$}  {:<10}  {:<16}  TARGET", "ID", "USES", "EXPIRES");
+
+	for link in &links {
+		let uses = match link.max_uses {
+			0 => format!("{}", link.invocations),
+			max_uses => format!("{}/{max_uses}", link.invocations),
+		};
+
+		println!("{:<id_width$}  {uses:<10}  {:<16}  {}", link.id, expiry(link), link.redirect_to);
+	}
+
+	println!("Showing {} of {total} links.", links.len());
+
+
+	Ok(())
+}
+
+/// When the link expires, in local time.
+fn expiry(link: &Link) -> String {
+	if link.is_expired() {
+		return "expired".to_owned();
+	}
+
+	match link.expires_at().and_then(|expires_at| Local.timestamp_millis_opt(expires_at).single()) {
+		Some(expires_at) => expires_at.format("%Y-%m-%d %H:%M").to_string(),
+		None => "never".to_owned(),
+	}
+}
+
+/// Writes all links to the file or stdout.
+///
+/// # Errors
+///
+/// Errors if the output can't be written or there is some problem communicating with the database.
+async fn export(link_store: &LinkStore, format: Format, output: Option<PathBuf>) -> Result<(), ShortyError> {
+	let mut output: Box<dyn AsyncWrite + Unpin> = match output {
+		Some(path) => Box::new(tokio::fs::File::create(path).await?),
+		None => Box::new(tokio::io::stdout()),
+	};
+
+	let mut chunks = pin!(link_store.export(format));
+	while let Some(chunk) = chunks.next().await {
+		output.write_all(&chunk?).await?;
+	}
+
+	output.flush().await?;
+
+
+	Ok(())
+}
+
+/// Reads links from the file or stdin and prints what happened to them.
+///
+/// # Errors
+///
+/// Errors if the input can't be read or there is some problem communicating with the database.
+async fn import(
+	link_store: &LinkStore,
+	format: Format,
+	conflict: ConflictStrategy,
+	input: Option<PathBuf>,
+) -> Result<(), ShortyError> {
+	let mut input: Box<dyn AsyncRead + Unpin> = match input {
+		Some(path) => Box::new(tokio::fs::File::open(path).await?),
+		None => Box::new(tokio::io::stdin()),
+	};
+
+	let mut decoder = RecordDecoder::new(format);
+	let mut importer = link_store.importer(conflict);
+	let mut buffer = vec![0; IMPORT_CHUNK_SIZE];
+
+	loop {
+		let read = input.read(&mut buffer).await?;
+		if read == 0 {
+			break;
+		}
+
+		for record in decoder.push(&buffer[..read]) {
+			importer.import(record).await?;
+		}
+	}
+
+	if let Some(record) = decoder.finish() {
+		importer.import(record).await?;
+	}
+
+	let summary = importer.finish();
+
+	for renamed in &summary.renamed {
+		println!("Renamed {} to {}", renamed.from, renamed.to);
+	}
+
+	for failure in &summary.failed {
+		println!("Line {}: {}", failure.line, failure.message);
+	}
+
+	println!(
+		"Imported {} links, overwrote {}, skipped {}, renamed {} and failed {}.",
+		summary.imported, summary.overwritten, summary.skipped, summary.renamed.len(), summary.failed.len(),
+	);
+
+
+	Ok(())
+}
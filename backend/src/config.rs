@@ -1,10 +1,165 @@
-use std::env::VarError;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+
 use serde::{Serialize, Deserialize};
-use tracing::error;
+use toml::{Spanned, Table, Value};
+use tracing::{error, info, warn};
+use url::Url;
 use utoipa::ToSchema;
 
+use crate::CONFIG;
+use crate::domain_filter::DOMAIN_FILTER;
+use crate::error::ShortyError;
+use crate::id_generator::IdAlphabet;
+use crate::link::RedirectType;
+use crate::rate_limit::RateLimitConfig;
+
 pub const SAMPLE_CONFIG: &str = include_str!(concat!(env!("OUT_DIR"), "/config.toml.sample"));
 
+/// How often the config file is checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Prefix of the environment variables that set config keys, like `SHORTY_PUBLIC_URL` for `public_url`.
+const ENV_PREFIX: &str = "SHORTY_";
+
+/// Environment variables with the prefix that aren't config keys.
+const NON_CONFIG_VARS: [&str; 2] = ["SHORTY_CONFIG", "SHORTY_WEBSITE"];
+
+/// The config file and command line overrides, set once on startup before the config gets loaded.
+static SOURCES: OnceLock<ConfigSources> = OnceLock::new();
+
+/// Where the value of a config key was set.
+#[derive(Debug, Clone)]
+enum Origin {
+	File { path: PathBuf, line: usize, column: usize },
+	Env(String),
+	CommandLine,
+	Default,
+	/// A key that only takes effect after a restart, which kept the value of the running config on a reload.
+	Running,
+	/// A key of a reloaded config, which doesn't fit the values of the running config that were kept.
+	Reloaded,
+}
+
+impl Display for Origin {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Origin::File { path, line, column } => write!(f, "{}:{line}:{column}", path.display()),
+			Origin::Env(var) => write!(f, "environment variable {var}"),
+			Origin::CommandLine => write!(f, "command line"),
+			Origin::Default => write!(f, "default"),
+			Origin::Running => write!(f, "running config"),
+			Origin::Reloaded => write!(f, "reloaded config"),
+		}
+	}
+}
+
+/// Something wrong with the value of a config key.
+#[derive(Debug)]
+struct Problem {
+	origin: Origin,
+	key: String,
+	message: String,
+}
+
+impl Problem {
+	fn new(origin: Origin, key: &str, message: &str) -> Self {
+		Self { origin, key: key.to_owned(), message: message.to_owned() }
+	}
+
+	/// Turns the problems into a single error, with one problem per line.
+	fn report(problems: Vec<Problem>) -> ShortyError {
+		let count = match problems.len() {
+			1 => "1 problem".to_owned(),
+			count => format!("{count} problems"),
+		};
+
+		let lines: String = problems.iter()
+			.map(|problem| format!("\n  {problem}"))
+			.collect();
+
+
+		ShortyError::Config(format!("{count}{lines}"))
+	}
+}
+
+impl Display for Problem {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: `{}` {}", self.origin, self.key, self.message)
+	}
+}
+
+/// The config that is in effect, which gets swapped as a whole when the config is reloaded.
+pub struct SharedConfig {
+	current: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+	#[must_use]
+	pub fn new(config: Config) -> Self {
+		Self { current: RwLock::new(Arc::new(config)) }
+	}
+
+	/// The config that is in effect right now.
+	/// Everything read from the returned config is consistent, even if it gets reloaded meanwhile.
+	///
+	/// # Panics
+	///
+	/// Panics if the lock of the config was poisoned.
+	#[must_use]
+	pub fn load(&self) -> Arc<Config> {
+		Arc::clone(&self.current.read().expect("Config lock was poisoned."))
+	}
+
+	fn store(&self, config: Config) {
+		*self.current.write().expect("Config lock was poisoned.") = Arc::new(config);
+	}
+}
+
+/// Where the config gets loaded from, besides the defaults and the `SHORTY_*` environment variables.
+#[derive(Debug, Default)]
+pub struct ConfigSources {
+	/// The config file, `SHORTY_CONFIG` or `config.toml` in the current directory if not set.
+	pub file: Option<PathBuf>,
+	/// Keys set on the command line, they take precedence over everything else.
+	pub overrides: Vec<(String, Value)>,
+}
+
+impl ConfigSources {
+	/// Makes these the sources [`Config::load`] uses, only the first call has an effect.
+	pub fn install(self) {
+		let _ = SOURCES.set(self);
+	}
+}
+
+/// The location of the config file and whether it was chosen explicitly.
+/// Only a config file that was chosen explicitly has to exist.
+#[must_use]
+pub fn config_file() -> (PathBuf, bool) {
+	if let Some(file) = SOURCES.get().and_then(|sources| sources.file.clone()) {
+		return (file, true);
+	}
+
+
+	match std::env::var("SHORTY_CONFIG") {
+		Ok(location) => (PathBuf::from(location), true),
+		Err(_) => (PathBuf::from("./config.toml"), false),
+	}
+}
+
+/// Parses a value from the environment or command line as TOML, like `8080`, `true` or `['a', 'b']`.
+/// Anything that isn't valid TOML, like most URLs and paths, is taken as a string.
+#[must_use]
+pub fn parse_value(raw: &str) -> Value {
+	toml::from_str::<Table>(format!("value = {raw}").as_str())
+		.ok()
+		.and_then(|mut table| table.remove("value"))
+		.unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct Config {
 	#[serde(default = "listen_url_default")]
@@ -12,12 +167,14 @@ pub struct Config {
 	pub listen_url: String,
 	/// The public URL that gets used for shortened links.
 	// It is different from the listen_url if shorty is run behind a reverse proxy.
+	// Defaults to localhost with the port, which is filled in by `Config::load`.
 	pub public_url: String,
 	/// The listen port.
 	#[serde(default = "port_default")]
 	#[serde(skip_serializing)]
 	pub port: u16,
 	/// The database connection String.
+	#[serde(default = "database_location_default")]
 	#[serde(skip_serializing)]
 	pub database_location: String,
 	/// The maximum length a link should be allowed to have.
@@ -35,36 +192,292 @@ pub struct Config {
 	/// Default duration a link is valid for.
 	#[serde(default = "valid_for_duration_default")]
 	pub default_valid_for: i64,
+	/// Default HTTP status code a link redirects with.
+	#[serde(default = "redirect_type_default")]
+	#[schema(value_type = u16)]
+	pub default_redirect_type: RedirectType,
+	/// Whether shortening the same link with the default settings returns the same shared link every time.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub deduplicate_links: bool,
+	/// How many characters random IDs start out with.
+	#[serde(default = "id_length_default")]
+	#[serde(skip_serializing)]
+	pub id_length: usize,
+	/// The characters random IDs are made of.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub id_alphabet: IdAlphabet,
 	/// Location for custom frontend.
 	#[serde(default)]
 	#[serde(skip_serializing)]
 	pub frontend_location: Option<String>,
+	/// Location of a GeoIP country database, used to record the country of visits.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub geoip_database: Option<String>,
+	/// Tokens that grant access to the admin API.
+	/// The admin API is unusable if none are configured.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub admin_tokens: Vec<String>,
+	/// Location of the PEM encoded certificate chain, enables HTTPS together with `tls_key`.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub tls_cert: Option<String>,
+	/// Location of the PEM encoded private key of the certificate.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub tls_key: Option<String>,
+	/// Port of an additional plain HTTP listener that redirects everything to HTTPS.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub http_redirect_port: Option<u16>,
+	/// Port of a separate listener for `/metrics`, which isn't served on the main port then.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub metrics_port: Option<u16>,
+	/// Whether the client IP is taken from the `X-Forwarded-For` header set by a reverse proxy.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub trust_x_forwarded_for: bool,
+	/// Budget of every client for creating links, unlimited if not set.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub rate_limit_creation: Option<RateLimitConfig>,
+	/// Budget of every client for following and inspecting links, unlimited if not set.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub rate_limit_resolution: Option<RateLimitConfig>,
+	/// Domains links aren't allowed to redirect to, `*.` in front of a domain includes its subdomains.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub blocked_domains: Vec<String>,
+	/// File with additional blocked domains, one per line. Changes are picked up while running.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub blocked_domains_file: Option<String>,
+	/// The only domains links are allowed to redirect to, if any are set.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub allowed_domains: Vec<String>,
+	/// File with additional allowed domains, one per line. Setting it enables the allowlist.
+	#[serde(default)]
+	#[serde(skip_serializing)]
+	pub allowed_domains_file: Option<String>,
 }
 
 impl Config {
+	/// Loads the config from the defaults, the config file, the `SHORTY_*` environment variables
+	/// and the command line, where each of them overrides the ones before it.
+	/// A missing config file is only an error if its location was chosen explicitly.
+	///
 	/// # Errors
-	/// Errors when the config couldn't be deserialized.
-	pub fn new(config: &str) -> Result<Self, toml::de::Error> {
-		let mut config: Config = toml::from_str(config)?;
-
-		if config.frontend_location.is_none() {
-			match std::env::var("SHORTY_WEBSITE") {
-				Ok(path) => { config.frontend_location = Some(path) },
-				#[allow(unused)]
-				Err(e) => {
-					#[cfg(not(feature = "integrated-frontend"))]
-					panic!("Shorty was compiled without the `integrated-frontend` feature, therefore the frontend_location key is mandatory");
-
-					if e != VarError::NotPresent {
-						error!("{e}");
-					}
-				},
+	///
+	/// Errors if the config file can't be read or the merged config is invalid.
+	/// All problems are reported at once, each along with where the key was set.
+	pub fn load() -> Result<Self, ShortyError> {
+		let (file, explicit) = config_file();
+
+		let content = match std::fs::read_to_string(&file) {
+			Ok(content) => Some(content),
+			Err(why) if explicit || why.kind() != std::io::ErrorKind::NotFound => {
+				return Err(ShortyError::Config(format!("Couldn't read {}: {why}", file.display())));
+			},
+			Err(_) => {
+				info!("There is no config file at {}, using the defaults and environment variables.", file.display());
+				None
+			},
+		};
+
+		let env: Vec<(String, String)> = std::env::vars().collect();
+		let overrides = SOURCES.get().map_or(&[][..], |sources| sources.overrides.as_slice());
+
+
+		Config::from_sources(content.as_deref().map(|content| (file.as_path(), content)), &env, overrides)
+	}
+
+	/// Merges the config file with its path and content, the environment variables and the command line overrides,
+	/// see [`Config::load`].
+	fn from_sources(
+		file: Option<(&Path, &str)>,
+		env: &[(String, String)],
+		overrides: &[(String, Value)],
+	) -> Result<Self, ShortyError> {
+		let mut table = Table::new();
+		let mut origins = HashMap::new();
+
+		if let Some((path, content)) = file {
+			let keys = toml::from_str::<BTreeMap<String, Spanned<Value>>>(content)
+				.map_err(|why| ShortyError::Config(format!("{}: {why}", path.display())))?;
+
+			for (key, value) in keys {
+				let (line, column) = line_and_column(content, value.span().start);
+				origins.insert(key.clone(), Origin::File { path: path.to_owned(), line, column });
+				table.insert(key, value.into_inner());
+			}
+		}
+
+		for (var, value) in env {
+			if NON_CONFIG_VARS.contains(&var.as_str()) {
+				continue;
 			}
+
+			if let Some(key) = var.strip_prefix(ENV_PREFIX) {
+				let key = key.to_lowercase();
+				table.insert(key.clone(), parse_value(value.as_str()));
+				origins.insert(key, Origin::Env(var.clone()));
+			}
+		}
+
+		for (key, value) in overrides {
+			table.insert(key.clone(), value.clone());
+			origins.insert(key.clone(), Origin::CommandLine);
 		}
 
+		if !table.contains_key("public_url") {
+			let port = table.get("port")
+				.and_then(Value::as_integer)
+				.unwrap_or_else(|| i64::from(port_default()));
+
+			table.insert("public_url".to_owned(), Value::String(format!("http://localhost:{port}")));
+		}
+
+		if !table.contains_key("frontend_location") {
+			if let Some((var, path)) = env.iter().find(|(var, _)| var == "SHORTY_WEBSITE") {
+				table.insert("frontend_location".to_owned(), Value::String(path.clone()));
+				origins.insert("frontend_location".to_owned(), Origin::Env(var.clone()));
+			}
+		}
+
+		let origin = |key: &str| origins.get(key).cloned().unwrap_or(Origin::Default);
+
+		// Every key is deserialized on its own first, so all keys with a wrong type get reported, not just the first.
+		let problems: Vec<Problem> = table.iter()
+			.filter_map(|(key, value)| {
+				let mut single = Table::new();
+				single.insert("public_url".to_owned(), Value::String(String::new()));
+				single.insert(key.clone(), value.clone());
+
+				single.try_into::<Config>()
+					.err()
+					.map(|why| Problem::new(origin(key), key, why.message().trim()))
+			})
+			.collect();
+
+		if !problems.is_empty() {
+			return Err(Problem::report(problems));
+		}
+
+		let config: Config = table.try_into()
+			.map_err(|why: toml::de::Error| ShortyError::Config(why.message().to_owned()))?;
+
+		let problems: Vec<Problem> = config.validate()
+			.into_iter()
+			.map(|(key, message)| Problem::new(origin(key), key, message.as_str()))
+			.collect();
+
+		if !problems.is_empty() {
+			return Err(Problem::report(problems));
+		}
+
+
 		Ok(config)
 	}
 
+	/// Checks the values of the keys and how they fit together.
+	/// Returns the problematic keys, along with what is wrong with them.
+	fn validate(&self) -> Vec<(&'static str, String)> {
+		let mut problems = Vec::new();
+		let mut check = |ok: bool, key: &'static str, message: &str| {
+			if !ok {
+				problems.push((key, message.to_owned()));
+			}
+		};
+
+		match Url::parse(self.public_url.as_str()) {
+			Ok(url) => {
+				check(
+					matches!(url.scheme(), "http" | "https") && url.has_host(),
+					"public_url",
+					"has to be an absolute http or https URL, like 'https://short.example.com'",
+				);
+				check(!self.public_url.ends_with('/'), "public_url", "mustn't end with a slash");
+			},
+			Err(why) => check(false, "public_url", format!("isn't a valid URL: {why}").as_str()),
+		}
+
+		check(self.max_link_length > 0, "max_link_length", "has to be positive");
+		check(self.max_json_size > 0, "max_json_size", "has to be positive");
+		check(self.max_custom_id_length > 0, "max_custom_id_length", "has to be positive");
+		check(self.id_length > 0, "id_length", "has to be positive");
+		check(
+			self.max_json_size >= self.max_link_length,
+			"max_json_size",
+			format!("has to be at least `max_link_length` ({}), otherwise long links can't be sent", self.max_link_length).as_str(),
+		);
+		check(self.default_max_uses >= 0, "default_max_uses", "mustn't be negative, 0 means unlimited uses");
+		check(self.default_valid_for >= 0, "default_valid_for", "mustn't be negative, 0 means links don't expire");
+		check(self.tls_cert.is_some() == self.tls_key.is_some(), "tls_key", "has to be set together with `tls_cert`");
+		check(self.metrics_port != Some(self.port), "metrics_port", "has to differ from `port`");
+
+		for (key, limit) in [
+			("rate_limit_creation", self.rate_limit_creation),
+			("rate_limit_resolution", self.rate_limit_resolution),
+		] {
+			if let Some(limit) = limit {
+				check(limit.burst > 0 && limit.per_minute > 0, key, "needs a positive `burst` and `per_minute`");
+			}
+		}
+
+		for (key, file) in [
+			("blocked_domains_file", &self.blocked_domains_file),
+			("allowed_domains_file", &self.allowed_domains_file),
+		] {
+			if let Some(path) = file {
+				let readable = Path::new(path).is_file() && std::fs::File::open(path).is_ok();
+				check(readable, key, format!("{path} isn't a readable file").as_str());
+			}
+		}
+
+		match &self.frontend_location {
+			Some(path) => check(Path::new(path).is_dir(), "frontend_location", format!("{path} isn't a directory").as_str()),
+			None => check(
+				cfg!(feature = "integrated-frontend"),
+				"frontend_location",
+				"has to be set, because shorty was compiled without the `integrated-frontend` feature",
+			),
+		}
+
+
+		problems
+	}
+
+	/// Takes over the keys that are only read on startup from the running config.
+	/// Returns the keys whose value differed, those changes only take effect after a restart.
+	fn keep_restart_only(&mut self, running: &Config) -> Vec<&'static str> {
+		let mut changed = Vec::new();
+
+		macro_rules! keep {
+			($($key:ident),+) => {
+				$(
+					if self.$key != running.$key {
+						changed.push(stringify!($key));
+						self.$key = running.$key.clone();
+					}
+				)+
+			};
+		}
+
+		keep!(
+			listen_url, port, database_location, max_json_size, geoip_database,
+			tls_cert, tls_key, http_redirect_port, metrics_port, rate_limit_creation, rate_limit_resolution
+		);
+
+
+		changed
+	}
+
 	#[allow(clippy::missing_panics_doc)]
 	#[must_use]
 	pub fn json_string(&self) -> String {
@@ -72,8 +485,102 @@ impl Config {
 	}
 }
 
+/// Loads the config again and swaps it in, along with the domain lists it points to.
+/// The current config is kept if the new one is invalid.
+/// Keys that are only read on startup, like `port`, keep their value and a warning is logged if they changed.
+///
+/// # Errors
+///
+/// Errors if the config can't be loaded or the domain lists can't be read.
+/// Also errors if the config is invalid together with the values that were kept.
+pub fn reload() -> Result<(), ShortyError> {
+	let mut config = Config::load()?;
+
+	let kept = config.keep_restart_only(&CONFIG.load());
+	for key in &kept {
+		warn!("`{key}` changed, which only takes effect after a restart.");
+	}
+
+	// The kept values might not fit the new ones, like a `max_json_size` that is too small for a new `max_link_length`.
+	let problems: Vec<Problem> = config.validate()
+		.into_iter()
+		.map(|(key, message)| {
+			let origin = if kept.contains(&key) { Origin::Running } else { Origin::Reloaded };
+			Problem::new(origin, key, message.as_str())
+		})
+		.collect();
+
+	if !problems.is_empty() {
+		return Err(Problem::report(problems));
+	}
+
+	CONFIG.store(config);
+	DOMAIN_FILTER.reload()?;
+
+
+	Ok(())
+}
+
+/// Reloads the config every time the process receives a SIGHUP.
+#[cfg(unix)]
+pub fn reload_on_sighup() {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	let mut hangup = signal(SignalKind::hangup()).expect("Error listening for SIGHUP.");
+
+	tokio::task::spawn(async move {
+		while hangup.recv().await.is_some() {
+			log_reload(reload());
+		}
+	});
+}
+
+/// Reloads the config whenever the config file changes.
+pub fn reload_on_change() {
+	tokio::task::spawn(async move {
+		let mut last_modified = modification_time();
+
+		loop {
+			tokio::time::sleep(RELOAD_INTERVAL).await;
+
+			let modified = modification_time();
+			if modified == last_modified {
+				continue;
+			}
+
+			last_modified = modified;
+			log_reload(reload());
+		}
+	});
+}
+
+/// The line and column of the byte offset in the text, both starting at 1.
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+	let before = &text[..offset.min(text.len())];
+	let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+
+
+	(before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+fn log_reload(result: Result<(), ShortyError>) {
+	match result {
+		Ok(()) => info!("Reloaded the config."),
+		Err(why) => error!("Keeping the old config: {why}"),
+	}
+}
+
+fn modification_time() -> Option<SystemTime> {
+	let (file, _) = config_file();
+
+
+	std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+}
+
 fn listen_url_default() -> String { env!("LISTEN_URL_DEFAULT").to_owned() }
 
+fn database_location_default() -> String { env!("DATABASE_LOCATION_DEFAULT").to_owned() }
+
 const fn port_default() -> u16 {
 	konst::unwrap_ctx!(konst::primitive::parse_u16(env!("PORT_DEFAULT")))
 }
@@ -89,6 +596,10 @@ const fn max_custom_id_length_default() -> usize {
 	konst::unwrap_ctx!(konst::primitive::parse_usize(env!("MAX_CUSTOM_ID_LENGTH_DEFAULT")))
 }
 
+const fn id_length_default() -> usize {
+	konst::unwrap_ctx!(konst::primitive::parse_usize(env!("ID_LENGTH_DEFAULT")))
+}
+
 // Link configuration default values
 
 const fn max_uses_default() -> i64 {
@@ -98,3 +609,78 @@ const fn max_uses_default() -> i64 {
 const fn valid_for_duration_default() -> i64 {
 	konst::unwrap_ctx!(konst::primitive::parse_i64(env!("VALID_FOR_DURATION_DEFAULT")))
 }
+
+fn redirect_type_default() -> RedirectType {
+	let status = konst::unwrap_ctx!(konst::primitive::parse_u16(env!("REDIRECT_TYPE_DEFAULT")));
+
+
+	RedirectType::try_from(status).expect("The default redirect type is invalid.")
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use toml::Value;
+
+	use super::Config;
+
+	fn problems(content: &str, env: &[(&str, &str)], overrides: &[(&str, Value)]) -> String {
+		let env: Vec<(String, String)> = env.iter()
+			.map(|(var, value)| ((*var).to_owned(), (*value).to_owned()))
+			.collect();
+		let overrides: Vec<(String, Value)> = overrides.iter()
+			.map(|(key, value)| ((*key).to_owned(), value.clone()))
+			.collect();
+
+		let Err(why) = Config::from_sources(Some((Path::new("config.toml"), content)), &env, &overrides) else {
+			panic!("The config is valid.");
+		};
+
+
+		why.to_string()
+	}
+
+	#[test]
+	fn wrong_types_are_reported_with_their_lines() {
+		let content = "\
+port = 'eighty'
+id_length = -1
+deduplicate_links = 'yes'
+";
+
+		let problems = problems(content, &[], &[]);
+
+		assert!(problems.starts_with("Config is invalid: 3 problems\n"), "{problems}");
+		for expected in [
+			"config.toml:1:8: `port` invalid type: string \"eighty\", expected u16",
+			"config.toml:2:13: `id_length` invalid value: integer `-1`, expected usize",
+			"config.toml:3:21: `deduplicate_links` invalid type: string \"yes\", expected a boolean",
+		] {
+			assert!(problems.lines().any(|line| line.trim() == expected), "{expected} is missing in {problems}");
+		}
+	}
+
+	#[test]
+	fn invalid_values_are_reported_with_their_origins() {
+		let content = "\
+public_url = 'ftp://short.example'
+max_link_length = 0
+port = 8080
+metrics_port = 8080
+";
+
+		let problems = problems(content, &[("SHORTY_DEFAULT_MAX_USES", "-1")], &[("id_length", Value::Integer(0))]);
+
+		assert!(problems.starts_with("Config is invalid: 5 problems\n"), "{problems}");
+		for expected in [
+			"config.toml:1:14: `public_url` has to be an absolute http or https URL, like 'https://short.example.com'",
+			"config.toml:2:19: `max_link_length` has to be positive",
+			"config.toml:4:16: `metrics_port` has to differ from `port`",
+			"environment variable SHORTY_DEFAULT_MAX_USES: `default_max_uses` mustn't be negative, 0 means unlimited uses",
+			"command line: `id_length` has to be positive",
+		] {
+			assert!(problems.lines().any(|line| line.trim() == expected), "{expected} is missing in {problems}");
+		}
+	}
+}
@@ -0,0 +1,292 @@
+use std::fs;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+use lazy_static::lazy_static;
+use tracing::{error, info, warn};
+use url::{Host, Url};
+
+use crate::CONFIG;
+use crate::error::ShortyError;
+
+/// How often the domain list files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+	pub static ref DOMAIN_FILTER: DomainFilter = DomainFilter::default();
+}
+
+/// A domain the filter applies to, like `example.com` or `*.example.com`.
+#[derive(Debug)]
+enum DomainPattern {
+	/// Only matches the domain itself.
+	Exact(String),
+	/// Matches the domain and all of its subdomains.
+	Wildcard(String),
+}
+
+impl DomainPattern {
+	/// Parses an entry of a domain list, the domain is normalized like the hosts of links are.
+	fn parse(entry: &str) -> Option<Self> {
+		let entry = entry.trim();
+
+		let (domain, wildcard) = match entry.strip_prefix("*.") {
+			Some(domain) => (domain, true),
+			None => (entry, false),
+		};
+
+		let domain = match normalize_domain(domain) {
+			Ok(domain) => domain,
+			Err(why) => {
+				warn!("Ignoring invalid domain '{entry}': {why}");
+				return None;
+			},
+		};
+
+
+		Some(if wildcard { Self::Wildcard(domain) } else { Self::Exact(domain) })
+	}
+
+	fn matches(&self, host: &str) -> bool {
+		match self {
+			Self::Exact(domain) => host == domain,
+			Self::Wildcard(domain) => host_matches_domain(host, domain),
+		}
+	}
+}
+
+/// The blocked and allowed domains at one point in time.
+#[derive(Debug, Default)]
+struct DomainRules {
+	blocked: Vec<DomainPattern>,
+	/// Every domain is allowed if there is no allowlist.
+	allowed: Option<Vec<DomainPattern>>,
+}
+
+impl DomainRules {
+	/// Reads the domains from the config and the domain list files.
+	fn load() -> Result<Self, ShortyError> {
+		let config = CONFIG.load();
+
+		let mut blocked = parse_entries(&config.blocked_domains);
+		if let Some(path) = &config.blocked_domains_file {
+			blocked.extend(read_domain_file(path)?);
+		}
+
+		let allowed = if config.allowed_domains.is_empty() && config.allowed_domains_file.is_none() {
+			None
+		} else {
+			let mut allowed = parse_entries(&config.allowed_domains);
+			if let Some(path) = &config.allowed_domains_file {
+				allowed.extend(read_domain_file(path)?);
+			}
+
+			Some(allowed)
+		};
+
+
+		Ok(Self { blocked, allowed })
+	}
+}
+
+/// Decides which domains links are allowed to redirect to.
+/// The default filter allows every domain, until the domain lists are loaded by [`DomainFilter::reload`].
+#[derive(Debug, Default)]
+pub struct DomainFilter {
+	rules: RwLock<DomainRules>,
+}
+
+impl DomainFilter {
+	/// Reads the domain lists from the config and the domain list files, the current ones are kept if that fails.
+	///
+	/// # Errors
+	///
+	/// Errors if one of the configured domain list files can't be read.
+	///
+	/// # Panics
+	///
+	/// Panics if the lock of the rules was poisoned.
+	pub fn reload(&self) -> Result<(), ShortyError> {
+		let rules = DomainRules::load()?;
+		*self.rules.write().expect("Domain filter lock was poisoned.") = rules;
+
+
+		Ok(())
+	}
+
+	/// Checks if links are allowed to redirect to the host of the link.
+	///
+	/// # Errors
+	///
+	/// Errors if the host is blocked or not on the allowlist.
+	///
+	/// # Panics
+	///
+	/// Panics if the lock of the rules was poisoned.
+	pub fn check(&self, link: &str) -> Result<(), ShortyError> {
+		let Some(host) = host_of(link) else {
+			return Ok(());
+		};
+
+		let rules = self.rules.read().expect("Domain filter lock was poisoned.");
+
+		if rules.blocked.iter().any(|pattern| pattern.matches(host.as_str())) {
+			return Err(ShortyError::DomainBlocked(host));
+		}
+
+		if let Some(allowed) = &rules.allowed {
+			if !allowed.iter().any(|pattern| pattern.matches(host.as_str())) {
+				return Err(ShortyError::DomainNotAllowed(host));
+			}
+		}
+
+
+		Ok(())
+	}
+
+	/// Checks if links are allowed to redirect to the host of the link.
+	#[must_use]
+	pub fn allows(&self, link: &str) -> bool {
+		self.check(link).is_ok()
+	}
+}
+
+/// The host of the link, normalized like domains are by [`normalize_domain`], which also covers old links.
+#[must_use]
+pub fn host_of(link: &str) -> Option<String> {
+	let url = Url::parse(link).ok()?;
+
+
+	url.host_str().map(|host| without_root_dot(host).to_owned())
+}
+
+/// Brings a domain into the form hosts of links have: lowercased, converted to punycode and
+/// without the trailing dot of fully qualified domains.
+///
+/// # Errors
+///
+/// Errors if the domain isn't a valid host.
+pub fn normalize_domain(domain: &str) -> Result<String, url::ParseError> {
+	Host::parse(without_root_dot(domain.trim())).map(|host| host.to_string())
+}
+
+/// Checks if the normalized host is the normalized domain itself or one of its subdomains.
+#[must_use]
+pub fn host_matches_domain(host: &str, domain: &str) -> bool {
+	host == domain || host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Removes the trailing dot of fully qualified domains like `example.com.`, which resolve to the same site.
+fn without_root_dot(domain: &str) -> &str {
+	domain.strip_suffix('.').unwrap_or(domain)
+}
+
+/// Checks that the domain list files can be read, without applying them.
+///
+/// # Errors
+///
+/// Errors if one of the configured domain list files can't be read.
+pub fn check_lists() -> Result<(), ShortyError> {
+	DomainRules::load().map(drop)
+}
+
+/// Reloads the domain lists whenever one of the domain list files changes.
+/// The files are looked up in the current config every time, so they can change when the config is reloaded.
+pub fn reload_on_change() {
+	tokio::task::spawn(async move {
+		let mut last_modified = modification_times();
+
+		loop {
+			tokio::time::sleep(RELOAD_INTERVAL).await;
+
+			let modified = modification_times();
+			if modified == last_modified {
+				continue;
+			}
+
+			last_modified = modified;
+
+			match DOMAIN_FILTER.reload() {
+				Ok(()) => info!("Reloaded the domain lists."),
+				Err(why) => error!("Keeping the old domain lists: {why}"),
+			}
+		}
+	});
+}
+
+fn modification_times() -> Vec<Option<SystemTime>> {
+	let config = CONFIG.load();
+
+
+	[&config.blocked_domains_file, &config.allowed_domains_file]
+		.into_iter()
+		.flatten()
+		.map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+		.collect()
+}
+
+fn parse_entries(entries: &[String]) -> Vec<DomainPattern> {
+	entries.iter()
+		.filter_map(|entry| DomainPattern::parse(entry))
+		.collect()
+}
+
+/// Reads a domain list file with one domain per line.
+/// Empty lines and lines starting with `#` are ignored.
+fn read_domain_file(path: &str) -> Result<Vec<DomainPattern>, ShortyError> {
+	let content = fs::read_to_string(path)
+		.map_err(|why| ShortyError::DomainList(format!("Couldn't read {path}: {why}")))?;
+
+
+	Ok(
+		content.lines()
+			.map(str::trim)
+			.filter(|line| !line.is_empty() && !line.starts_with('#'))
+			.filter_map(DomainPattern::parse)
+			.collect()
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{DomainPattern, host_matches_domain, host_of, normalize_domain};
+
+	fn matches(pattern: &str, link: &str) -> bool {
+		let pattern = DomainPattern::parse(pattern).unwrap();
+
+
+		host_of(link).is_some_and(|host| pattern.matches(host.as_str()))
+	}
+
+	#[test]
+	fn exact_pattern_matches_only_the_domain() {
+		assert!(matches("evil.com", "https://evil.com/path"));
+		assert!(matches("evil.com", "https://EVIL.com"));
+		assert!(!matches("evil.com", "https://sub.evil.com"));
+		assert!(!matches("evil.com", "https://notevil.com"));
+	}
+
+	#[test]
+	fn wildcard_pattern_matches_subdomains() {
+		assert!(matches("*.evil.com", "https://evil.com"));
+		assert!(matches("*.evil.com", "https://a.b.evil.com"));
+		assert!(!matches("*.evil.com", "https://notevil.com"));
+	}
+
+	#[test]
+	fn trailing_dot_is_ignored() {
+		assert!(matches("evil.com", "http://evil.com./"));
+		assert!(matches("*.evil.com", "http://sub.evil.com./"));
+		assert!(matches("evil.com.", "http://evil.com/"));
+		assert!(matches("*.evil.com.", "http://sub.evil.com/"));
+	}
+
+	#[test]
+	fn domains_are_normalized_like_hosts() {
+		assert_eq!(normalize_domain("Bücher.de").unwrap(), "xn--bcher-kva.de");
+		assert_eq!(normalize_domain("evil.com.").unwrap(), "evil.com");
+		assert_eq!(host_of("https://BÜCHER.de./x").unwrap(), "xn--bcher-kva.de");
+		assert!(host_matches_domain(host_of("http://a.evil.com./").unwrap().as_str(), "evil.com"));
+		assert!(!host_matches_domain("notevil.com", "evil.com"));
+	}
+}
@@ -1,39 +1,121 @@
 use actix_files::NamedFile;
-use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
-use tracing::{debug, info};
-use utoipa::OpenApi;
+use actix_web::{delete, Either, get, HttpRequest, HttpResponse, HttpResponseBuilder, patch, post, Responder, web};
+use actix_web::http::header::LOCATION;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
 
+use crate::admin::{self, LinkList, PurgeRequest, PurgeResponse};
 use crate::CONFIG;
 use crate::config::Config;
-use crate::error::ShortyError;
+use crate::error::{ErrorCode, ErrorResponse, ShortyError};
 use crate::LinkConfig;
 use crate::LinkStore;
-use crate::util::uri_to_url;
+use crate::link::{BatchItem, Link, LinkInfo, LinkResponse, LinkUpdate};
+use crate::metrics::METRICS;
+use crate::rate_limit::{self, RateLimit};
+use crate::storage::Storage;
+use crate::transfer::{ConflictStrategy, Format, ImportFailure, ImportSummary, RenamedLink};
+use crate::util::{bearer_token, escape_html, prefers_json, uri_to_url};
+use crate::visit::{StatsInterval, StatsQuery, VisitBucket, VisitCount, VisitStats, VisitStore};
+
+/// Response header that carries the management token of a freshly created link.
+pub const MANAGEMENT_TOKEN_HEADER: &str = "Shorty-Management-Token";
+
+/// Page that asks for the password of a password protected link.
+const UNLOCK_PAGE: &str = include_str!("unlock.html");
 
 #[derive(OpenApi)]
 #[openapi(
 	paths(
 		get_shortened,
+		unlock,
+		get_info,
+		get_stats,
 		get_config,
+		get_metrics,
+		healthz,
+		readyz,
 		create_shortened,
 		create_shortened_custom,
+		create_shortened_batch,
+		update_managed,
+		delete_managed,
+		admin::list_links,
+		admin::expire_link,
+		admin::delete_link,
+		admin::purge_domain,
+		admin::export_links,
+		admin::import_links,
 	),
+	components(schemas(
+		LinkConfig, LinkUpdate, LinkResponse, LinkInfo, UnlockRequest, ErrorResponse, ErrorCode,
+		BatchRequest, BatchItemResponse, BatchResponse,
+		VisitStats, VisitBucket, VisitCount, StatsInterval,
+		LinkList, PurgeRequest, PurgeResponse, HealthStatus, Health, Readiness,
+		Format, ConflictStrategy, ImportSummary, RenamedLink, ImportFailure,
+	)),
 	tags(
 		(name = "/", description = "Simple shortening"),
 		(name = "/custom", description = "Advanced shortening"),
+		(name = "/unlock", description = "Following password protected links"),
 		(name = "/config", description = "Server configuration"),
-	)
+		(name = "/metrics", description = "Monitoring with Prometheus"),
+		(name = "/health", description = "Liveness and readiness checks for orchestrators"),
+		(name = "/info", description = "Inspecting links without following them"),
+		(name = "/stats", description = "Visit statistics of links"),
+		(name = "/admin", description = "Moderation of all links, requires an admin token"),
+		(name = "/manage", description = "Editing and deleting links with their management token"),
+	),
+	modifiers(&SecurityAddon),
 )]
 pub struct ApiDoc;
 
+/// Registers the authentication schemes used by the endpoints in the OpenAPI spec.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+	fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+		if let Some(components) = openapi.components.as_mut() {
+			components.add_security_scheme(
+				"management_token",
+				SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+			);
+			components.add_security_scheme(
+				"admin_token",
+				SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+			);
+		}
+	}
+}
+
 // The function is async because the actix-web macro requires it.
 #[allow(clippy::unused_async)]
 #[get("/")]
 pub async fn index(req: HttpRequest) -> Result<impl Responder, Box<dyn std::error::Error>> {
 	debug!("Got request for Index");
-	if let Some(ref path) = CONFIG.frontend_location {
+
+
+	index_response(&req)
+}
+
+/// Serves the frontend, which renders a preview of the link instead of redirecting to it.
+// The function is async because the actix-web macro requires it.
+#[allow(clippy::unused_async)]
+#[get("/{link_id}+")]
+pub async fn preview(req: HttpRequest) -> Result<impl Responder, Box<dyn std::error::Error>> {
+	debug!("Got request for preview of {}", req.match_info().query("link_id"));
+
+
+	index_response(&req)
+}
+
+/// Responds with the `index.html` of the frontend.
+fn index_response(req: &HttpRequest) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+	if let Some(ref path) = CONFIG.load().frontend_location {
 		let path = format!("{path}/index.html");
-		return Ok(NamedFile::open(path)?.into_response(&req));
+		return Ok(NamedFile::open(path)?.into_response(req));
 	}
 
 	#[cfg(feature = "integrated-frontend")]
@@ -60,14 +142,22 @@ pub async fn index(req: HttpRequest) -> Result<impl Responder, Box<dyn std::erro
 		description = "The id of the aliased url",
 	)),
 	responses(
-		(status = 307, description = "Redirection to aliased url"),
+		(status = 307, description = "Redirection to aliased url, the status code depends on the redirect type of the link"),
+		(status = 301, description = "Permanent redirection to aliased url"),
+		(status = 302, description = "Temporary redirection to aliased url"),
+		(status = 303, description = "Temporary redirection to aliased url that always uses GET"),
+		(status = 308, description = "Permanent redirection to aliased url"),
+		(status = 200, content_type = "text/html", description = "The link is password protected, a page asking for the password is returned"),
 		(status = 404, description = "Shortened ID couldn't be found or was expired"),
+		(status = 429, description = "Too many requests were made from the client's IP address"),
 	),
 )]
-#[get("/{link_id:.*}")]
+#[get("/{link_id:.*}", wrap = "RateLimit::resolution()")]
 async fn get_shortened(
+	req: HttpRequest,
 	params: web::Path<String>,
 	link_store: web::Data<LinkStore>,
+	visit_store: web::Data<VisitStore>,
 ) -> Result<impl Responder, ShortyError> {
 	let link_id = params.into_inner();
 	debug!("Got request for {link_id}");
@@ -75,16 +165,192 @@ async fn get_shortened(
 
 	if let Some(link) = link_store.get(link_id.as_str()).await {
 		info!("Return url for {link_id} is {link}");
+		record_visit(&link, &req, &visit_store).await;
+		METRICS.redirect();
+
 		Ok(
-			HttpResponse::TemporaryRedirect()
+			HttpResponse::build(link.redirect_type().status_code())
 				.append_header(("Location", link.redirect_to.as_str()))
 				.finish()
 		)
+	} else if let Some(link) = link_store.locked(link_id.as_str()).await? {
+		debug!("{link_id} is password protected, asking for the password");
+
+		Ok(unlock_page(&link, HttpResponse::Ok(), None))
 	} else {
 		Ok(HttpResponse::NotFound().finish())
 	}
 }
 
+/// The password for a password protected link.
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({"password": "hunter2"}))]
+pub struct UnlockRequest {
+	pub password: String,
+}
+
+/// Unlock a password protected link
+///
+/// Redirects to the target of a password protected link if the password is correct.
+/// Accepts the password from the unlock page form as well as JSON.
+#[utoipa::path(
+	tag = "/unlock",
+	params((
+		"link_id" = inline(String),
+		Path,
+		description = "The id of the password protected link",
+	)),
+	request_body(content = UnlockRequest, description = "The password of the link", content_type = "application/x-www-form-urlencoded"),
+	responses(
+		(status = 303, description = "The password is correct, redirection to aliased url"),
+		(status = 403, description = "The password is incorrect", content(
+			("text/html" = String),
+			("application/json" = ErrorResponse),
+		)),
+		(status = 404, body = ErrorResponse, description = "Shortened ID couldn't be found, was expired or isn't password protected"),
+		(status = 429, body = ErrorResponse, description = "Too many requests were made from the client's IP address"),
+	),
+)]
+#[post("/unlock/{link_id:.*}", wrap = "RateLimit::resolution()")]
+async fn unlock(
+	req: HttpRequest,
+	params: web::Path<String>,
+	unlock: Either<web::Form<UnlockRequest>, web::Json<UnlockRequest>>,
+	link_store: web::Data<LinkStore>,
+	visit_store: web::Data<VisitStore>,
+) -> Result<impl Responder, ShortyError> {
+	let link_id = params.into_inner();
+	let password = match unlock {
+		Either::Left(form) => form.into_inner().password,
+		Either::Right(json) => json.into_inner().password,
+	};
+
+	match link_store.unlock(link_id.as_str(), password.as_str()).await {
+		Ok(link) => {
+			info!("Unlocked {link_id}, return url is {link}");
+			record_visit(&link, &req, &visit_store).await;
+			METRICS.redirect();
+
+			Ok(
+				HttpResponse::SeeOther()
+					.append_header((LOCATION, link.redirect_to.as_str()))
+					.finish()
+			)
+		},
+		// Browsers get to try again, API clients get the usual error.
+		Err(err @ ShortyError::InvalidPassword) if !prefers_json(&req) => {
+			debug!("Wrong password for {link_id}");
+
+			match link_store.locked(link_id.as_str()).await? {
+				Some(link) => Ok(unlock_page(&link, HttpResponse::Forbidden(), Some(err.to_string().as_str()))),
+				None => Err(ShortyError::LinkNotFound),
+			}
+		},
+		Err(err) => Err(err),
+	}
+}
+
+/// Records a visit of the link, a failure to do so shouldn't keep anyone from being redirected.
+async fn record_visit(link: &Link, req: &HttpRequest, visit_store: &VisitStore) {
+	if let Err(why) = visit_store.record(link.id.as_str(), req).await {
+		error!("Failed to record visit of {}: {why}", link.id);
+	}
+}
+
+/// Finishes the response with the page asking for the password of the link.
+fn unlock_page(link: &Link, mut response: HttpResponseBuilder, error: Option<&str>) -> HttpResponse {
+	let action = format!("{}/unlock/{}", CONFIG.load().public_url, link.id);
+	let error = error
+		.map(|error| format!(r#"<span class="error">{}</span>"#, escape_html(error)))
+		.unwrap_or_default();
+
+	let page = UNLOCK_PAGE
+		.replace("{{action}}", escape_html(action.as_str()).as_str())
+		.replace("{{link_id}}", escape_html(link.id.as_str()).as_str())
+		.replace("{{error}}", error.as_str());
+
+
+	response
+		.content_type("text/html; charset=utf-8")
+		.body(page)
+}
+
+/// Inspect a link
+///
+/// Shows where a link leads and how long it stays valid, without counting as a use of the link.
+#[utoipa::path(
+	tag = "/info",
+	params((
+		"link_id" = inline(String),
+		Path,
+		description = "The id of the link to inspect",
+	)),
+	responses(
+		(status = 200, body = LinkInfo, description = "Information about the link"),
+		(status = 401, body = ErrorResponse, description = "The link is password protected, so its target isn't revealed"),
+		(status = 404, body = ErrorResponse, description = "Shortened ID couldn't be found or was expired"),
+		(status = 429, body = ErrorResponse, description = "Too many requests were made from the client's IP address"),
+	),
+)]
+#[get("/info/{link_id}", wrap = "RateLimit::resolution()")]
+async fn get_info(
+	params: web::Path<String>,
+	link_store: web::Data<LinkStore>,
+) -> Result<impl Responder, ShortyError> {
+	let link_id = params.into_inner();
+	debug!("Got info request for {link_id}");
+
+	let link = link_store.inspect(link_id.as_str()).await?;
+
+	if link.is_password_protected() {
+		return Err(ShortyError::PasswordRequired);
+	}
+
+
+	Ok(HttpResponse::Ok().json(LinkInfo::from(&link)))
+}
+
+/// Visit statistics of a link
+///
+/// Counts the visits of a link in time buckets and breaks them down by referrer,
+/// user agent and country.
+/// Requires the management token that was returned when the link was created.
+#[utoipa::path(
+	tag = "/stats",
+	params(
+		(
+			"link_id" = inline(String),
+			Path,
+			description = "The id of the link",
+		),
+		StatsQuery,
+	),
+	responses(
+		(status = 200, body = VisitStats, description = "The visit statistics of the link"),
+		(status = 401, body = ErrorResponse, description = "No management token was provided"),
+		(status = 403, body = ErrorResponse, description = "The management token doesn't match the link"),
+		(status = 404, body = ErrorResponse, description = "Shortened ID couldn't be found or was expired"),
+	),
+	security(("management_token" = [])),
+)]
+#[get("/stats/{link_id}")]
+async fn get_stats(
+	req: HttpRequest,
+	params: web::Path<String>,
+	query: web::Query<StatsQuery>,
+	link_store: web::Data<LinkStore>,
+	visit_store: web::Data<VisitStore>,
+) -> Result<impl Responder, ShortyError> {
+	let link_id = params.into_inner();
+	let token = bearer_token(&req).ok_or(ShortyError::ManagementTokenMissing)?;
+
+	let link = link_store.get_managed(link_id.as_str(), token).await?;
+	let stats = visit_store.stats(link.id.as_str(), query.interval).await?;
+
+
+	Ok(HttpResponse::Ok().json(stats))
+}
+
 /// Retrieves the servers configuration details
 #[utoipa::path(
 	tag = "/config",
@@ -98,7 +364,106 @@ async fn get_shortened(
 async fn get_config() -> impl Responder {
 	HttpResponse::Ok()
 		.content_type("application/json; charset=utf-8")
-		.body(CONFIG.json_string())
+		.body(CONFIG.load().json_string())
+}
+
+/// Metrics of the server in the Prometheus text format
+///
+/// Served on `metrics_port` instead if it is configured.
+#[utoipa::path(
+	tag = "/metrics",
+	responses(
+		(status = 200, content_type = "text/plain", body = String, description = "The metrics in the Prometheus text format"),
+	),
+)]
+#[get("/metrics")]
+pub async fn get_metrics(link_store: web::Data<LinkStore>) -> Result<impl Responder, ShortyError> {
+	let metrics = link_store.metrics().await?;
+
+
+	Ok(
+		HttpResponse::Ok()
+			.content_type("text/plain; version=0.0.4; charset=utf-8")
+			.body(metrics)
+	)
+}
+
+/// Whether something is working.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+	Ok,
+	Unavailable,
+}
+
+/// Whether the process is alive.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({"status": "ok"}))]
+pub struct Health {
+	pub status: HealthStatus,
+}
+
+/// Whether the server is ready to handle requests.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({"status": "ok", "database": "ok", "pending_migrations": []}))]
+pub struct Readiness {
+	/// Ok if the database is reachable and its schema is up to date.
+	pub status: HealthStatus,
+	/// Whether the database can be reached.
+	pub database: HealthStatus,
+	/// The versions of the migrations that weren't applied to the database yet.
+	pub pending_migrations: Vec<i64>,
+}
+
+/// Liveness check
+///
+/// Answers as long as the process is running, without checking anything else.
+#[utoipa::path(
+	tag = "/health",
+	responses(
+		(status = 200, body = Health, description = "The process is alive"),
+	),
+)]
+// The function is async because the actix-web macro requires it.
+#[allow(clippy::unused_async)]
+#[get("/healthz")]
+pub async fn healthz() -> impl Responder {
+	HttpResponse::Ok().json(Health { status: HealthStatus::Ok })
+}
+
+/// Readiness check
+///
+/// Checks that the database is reachable and all migrations are applied to it.
+#[utoipa::path(
+	tag = "/health",
+	responses(
+		(status = 200, body = Readiness, description = "The server is ready to handle requests"),
+		(status = 503, body = Readiness, description = "The database can't be reached or its schema isn't up to date"),
+	),
+)]
+#[get("/readyz")]
+pub async fn readyz(storage: web::Data<dyn Storage>) -> impl Responder {
+	let (database, pending_migrations) = match storage.pending_migrations().await {
+		Ok(pending) => (HealthStatus::Ok, pending),
+		Err(why) => {
+			error!("Readiness check failed: {why}");
+			(HealthStatus::Unavailable, Vec::new())
+		},
+	};
+
+	let status = if database == HealthStatus::Ok && pending_migrations.is_empty() {
+		HealthStatus::Ok
+	} else {
+		HealthStatus::Unavailable
+	};
+
+	let mut response = match status {
+		HealthStatus::Ok => HttpResponse::Ok(),
+		HealthStatus::Unavailable => HttpResponse::ServiceUnavailable(),
+	};
+
+
+	response.json(Readiness { status, database, pending_migrations })
 }
 
 /// Create a simple, unconfigured shortened link.
@@ -110,10 +475,17 @@ async fn get_config() -> impl Responder {
 		description = "The url to shorten",
 	)),
 	responses(
-		(status = 200, description = "The url was successfully shortened"),
+		(status = 200, description = "The url was successfully shortened", content(
+			("text/plain" = String),
+			("application/json" = LinkResponse),
+		), headers(
+			("Shorty-Management-Token" = String, description = "Secret token that allows editing or deleting the link, absent if the link is shared because deduplication is enabled"),
+		)),
+		(status = 400, body = ErrorResponse, description = "The link exceeds the max length allowed by the server, the link was empty, isn't a valid http(s) URL or its domain isn't allowed"),
+		(status = 429, body = ErrorResponse, description = "Too many links were created from the client's IP address"),
 	),
 )]
-#[post("/{url:.*}")]
+#[post("/{url:.*}", wrap = "RateLimit::creation()")]
 #[allow(clippy::similar_names)]
 async fn create_shortened(
 	req: HttpRequest,
@@ -123,16 +495,16 @@ async fn create_shortened(
 	debug!("URI is {uri}");
 	let url = uri_to_url(uri);
 
-	let link = link_store.create_link(url).await?;
-	let formatted = link.formatted();
-	info!("Shortening URL {} to {}", link.redirect_to, formatted);
+	let created = link_store.create_link(url).await?;
+	info!("Shortening URL {} to {}", created.link.redirect_to, created.link.formatted());
 
+	let mut response = HttpResponse::Ok();
+	if let Some(management_token) = &created.management_token {
+		response.insert_header((MANAGEMENT_TOKEN_HEADER, management_token.as_str()));
+	}
 
-	Ok(
-		HttpResponse::Ok()
-			.content_type("text/plain; charset=utf-8")
-			.body(formatted)
-	)
+
+	Ok(link_response(&req, response, LinkResponse::from(&created)))
 }
 
 /// Advanced url shortening
@@ -142,28 +514,212 @@ async fn create_shortened(
 	tag = "/custom",
 	request_body(content = inline(LinkConfig), description = "The settings for the url to alias"),
 	responses(
-		(status = 200, description = "The url was successfully registered as an alias and is now retrievable with at the get endpoint"),
-		(status = 400, description = "Json is malformed, the link exceeds the max length allowed by the server or the link was empty"),
-		(status = 409, description = "The specified ID is already in use"),
+		(status = 200, description = "The url was successfully registered as an alias and is now retrievable with at the get endpoint", content(
+			("text/plain" = String),
+			("application/json" = LinkResponse),
+		), headers(
+			("Shorty-Management-Token" = String, description = "Secret token that allows editing or deleting the link"),
+		)),
+		(status = 400, body = ErrorResponse, description = "Json is malformed, the link exceeds the max length allowed by the server, the link was empty, isn't a valid http(s) URL or its domain isn't allowed"),
+		(status = 409, body = ErrorResponse, description = "The specified ID is already in use"),
+		(status = 413, body = ErrorResponse, description = "The json exceeds the max size allowed by the server"),
+		(status = 429, body = ErrorResponse, description = "Too many links were created from the client's IP address"),
 	),
 )]
-#[post("/custom")]
+#[post("/custom", wrap = "RateLimit::creation()")]
 async fn create_shortened_custom(
+	req: HttpRequest,
 	link_store: web::Data<LinkStore>,
 	link_config: web::Json<LinkConfig>,
 ) -> Result<impl Responder, ShortyError> {
 	let link_config = link_config.into_inner();
 
-	let link = link_store.create_link_with_config(link_config).await?;
-	let formatted = link.formatted();
-	info!("Shortening URL {} to {}", link.redirect_to, formatted);
+	let created = link_store.create_link_with_config(link_config).await?;
+	info!("Shortening URL {} to {}", created.link.redirect_to, created.link.formatted());
 
+	let mut response = HttpResponse::Ok();
+	if let Some(management_token) = &created.management_token {
+		response.insert_header((MANAGEMENT_TOKEN_HEADER, management_token.as_str()));
+	}
 
-	Ok(
+
+	Ok(link_response(&req, response, LinkResponse::from(&created)))
+}
+
+/// Several links to shorten at once.
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(example = json!({
+	"links": [
+		{"link": "https://google.com", "custom_id": "search"},
+		{"link": "https://duckduckgo.com", "max_uses": 10}
+	],
+	"all_or_nothing": true
+}))]
+pub struct BatchRequest {
+	/// The settings of every link, the same as for a single link.
+	pub links: Vec<LinkConfig>,
+	/// Create none of the links if any of them fails.
+	#[serde(default)]
+	pub all_or_nothing: bool,
+}
+
+/// What happened to one link of a batch.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchItemResponse {
+	/// The link was created.
+	Created { link: LinkResponse },
+	/// The link couldn't be created.
+	Failed { error: ErrorResponse },
+	/// The link would have been created, but another link failed and `all_or_nothing` was set.
+	RolledBack,
+}
+
+/// The results of a batch, in the same order as the links of the request.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResponse {
+	/// How many links were created.
+	pub created: usize,
+	pub results: Vec<BatchItemResponse>,
+}
+
+/// Batch url shortening
+///
+/// Shortens several URLs at once, with the same settings as for advanced shortening.
+/// All links are created in a single transaction.
+/// The management token of every created link is part of its result.
+#[utoipa::path(
+	tag = "/custom",
+	request_body(content = BatchRequest, description = "The settings for the urls to alias"),
+	responses(
+		(status = 200, body = BatchResponse, description = "The result of every link, each of them was created or failed on its own"),
+		(status = 400, body = BatchResponse, description = "A link failed and `all_or_nothing` was set, so none were created"),
+		(status = 413, body = ErrorResponse, description = "The json exceeds the max size allowed by the server"),
+		(status = 429, body = ErrorResponse, description = "The client's IP address doesn't have enough of its creation budget left for all links of the batch"),
+	),
+)]
+#[post("/custom/batch")]
+async fn create_shortened_batch(
+	req: HttpRequest,
+	link_store: web::Data<LinkStore>,
+	batch: web::Json<BatchRequest>,
+) -> Result<impl Responder, ShortyError> {
+	let BatchRequest { links, all_or_nothing } = batch.into_inner();
+	let total = links.len();
+
+	// Every link of the batch counts against the creation budget, like it would if it was created on its own.
+	rate_limit::charge_creation(&req, total)?;
+
+	let items = link_store.create_links(links, all_or_nothing).await?;
+
+	let results: Vec<BatchItemResponse> = items.iter()
+		.map(|item| match item {
+			BatchItem::Created(created) => BatchItemResponse::Created { link: LinkResponse::from(created) },
+			BatchItem::Failed(why) => BatchItemResponse::Failed { error: ErrorResponse::from(why) },
+			BatchItem::RolledBack => BatchItemResponse::RolledBack,
+		})
+		.collect();
+	let created = items.iter()
+		.filter(|item| matches!(item, BatchItem::Created(_)))
+		.count();
+	info!("Shortened {created} of {total} URLs in a batch");
+
+	let mut response = if all_or_nothing && created < total {
+		HttpResponse::BadRequest()
+	} else {
 		HttpResponse::Ok()
+	};
+
+
+	Ok(response.json(BatchResponse { created, results }))
+}
+
+/// Edit a link
+///
+/// Changes the target, max uses or lifetime of a link.
+/// Requires the management token that was returned when the link was created.
+#[utoipa::path(
+	tag = "/manage",
+	params((
+		"link_id" = inline(String),
+		Path,
+		description = "The id of the link to edit",
+	)),
+	request_body(content = inline(LinkUpdate), description = "The settings that should be changed"),
+	responses(
+		(status = 200, description = "The link was updated", content(
+			("text/plain" = String),
+			("application/json" = LinkResponse),
+		)),
+		(status = 400, body = ErrorResponse, description = "Json is malformed, the link is invalid or the update would expire the link"),
+		(status = 401, body = ErrorResponse, description = "No management token was provided"),
+		(status = 403, body = ErrorResponse, description = "The management token doesn't match the link"),
+		(status = 404, body = ErrorResponse, description = "Shortened ID couldn't be found or was expired"),
+	),
+	security(("management_token" = [])),
+)]
+#[patch("/manage/{link_id}")]
+async fn update_managed(
+	req: HttpRequest,
+	params: web::Path<String>,
+	link_store: web::Data<LinkStore>,
+	update: web::Json<LinkUpdate>,
+) -> Result<impl Responder, ShortyError> {
+	let link_id = params.into_inner();
+	let token = bearer_token(&req).ok_or(ShortyError::ManagementTokenMissing)?;
+
+	let link = link_store.update_link(link_id.as_str(), token, update.into_inner()).await?;
+	info!("Updated {link_id}, it now redirects to {link}");
+
+
+	Ok(link_response(&req, HttpResponse::Ok(), LinkResponse::from(&link)))
+}
+
+/// Delete a link
+///
+/// Revokes a link before it expires.
+/// Requires the management token that was returned when the link was created.
+#[utoipa::path(
+	tag = "/manage",
+	params((
+		"link_id" = inline(String),
+		Path,
+		description = "The id of the link to delete",
+	)),
+	responses(
+		(status = 204, description = "The link was deleted"),
+		(status = 401, body = ErrorResponse, description = "No management token was provided"),
+		(status = 403, body = ErrorResponse, description = "The management token doesn't match the link"),
+		(status = 404, body = ErrorResponse, description = "Shortened ID couldn't be found or was expired"),
+	),
+	security(("management_token" = [])),
+)]
+#[delete("/manage/{link_id}")]
+async fn delete_managed(
+	req: HttpRequest,
+	params: web::Path<String>,
+	link_store: web::Data<LinkStore>,
+) -> Result<impl Responder, ShortyError> {
+	let link_id = params.into_inner();
+	let token = bearer_token(&req).ok_or(ShortyError::ManagementTokenMissing)?;
+
+	link_store.delete_link(link_id.as_str(), token).await?;
+	info!("Deleted {link_id}");
+
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Finishes the response with the link as JSON if the client asked for it,
+/// otherwise just with the shortened link as plain text.
+fn link_response(req: &HttpRequest, mut response: HttpResponseBuilder, link: LinkResponse) -> HttpResponse {
+	if prefers_json(req) {
+		response.json(link)
+	} else {
+		response
 			.content_type("text/plain; charset=utf-8")
-			.body(formatted)
-	)
+			.body(link.short_url)
+	}
 }
 
 #[allow(clippy::unused_async)]
@@ -180,7 +736,7 @@ pub async fn serve_file(asset: web::Path<String>, req: HttpRequest) -> Result<im
 	let asset = asset.into_inner();
 	debug!("Got request for file: {asset}");
 
-	if let Some(ref path) = CONFIG.frontend_location {
+	if let Some(ref path) = CONFIG.load().frontend_location {
 		let path = format!("{path}/{asset}");
 		return Ok(NamedFile::open(path)?.into_response(&req));
 	}
@@ -226,3 +782,56 @@ fn get_embedded_file(file: &str) -> Option<(&'static str, &'static [u8])> {
 	})
 }
 
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use actix_web::{App, test, web};
+	use actix_web::http::StatusCode;
+	use futures_util::future::join_all;
+
+	use crate::link::{LinkConfig, LinkStore};
+	use crate::storage::sqlite::SqliteStorage;
+	use crate::storage::Storage;
+	use crate::visit::VisitStore;
+
+	use super::get_shortened;
+
+	const CONCURRENT_REQUESTS: usize = 50;
+
+	#[actix_web::test]
+	async fn single_use_link_redirects_once() {
+		let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::in_memory().await.unwrap());
+		storage.migrate().await.unwrap();
+
+		let links = web::Data::new(LinkStore::new(storage.clone()));
+		let link_config = LinkConfig::new("https://example.com".to_owned(), Some("once".to_owned()), Some(1), None, None, None);
+		links.create_link_with_config(link_config).await.unwrap();
+
+		let app = test::init_service(
+			App::new()
+				.app_data(links.clone())
+				.app_data(web::Data::new(VisitStore::new(storage.clone(), None)))
+				.service(get_shortened)
+		).await;
+
+		let responses = join_all((0..CONCURRENT_REQUESTS).map(|_| {
+			test::call_service(&app, test::TestRequest::get().uri("/once").to_request())
+		})).await;
+
+		let redirects = responses.iter()
+			.filter(|response| response.status().is_redirection())
+			.count();
+		let not_found = responses.iter()
+			.filter(|response| response.status() == StatusCode::NOT_FOUND)
+			.count();
+
+		assert_eq!(redirects, 1);
+		assert_eq!(not_found, CONCURRENT_REQUESTS - 1);
+
+		// The used up link is stale now and gets cleaned up.
+		assert_eq!(links.clean().await.unwrap(), 1);
+		assert!(storage.get_link("once").await.unwrap().is_none());
+	}
+}
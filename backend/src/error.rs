@@ -1,7 +1,14 @@
 use actix_web::{HttpResponse, HttpResponseBuilder, ResponseError};
 use actix_web::body::BoxBody;
+use actix_web::error::JsonPayloadError;
+use actix_web::http::header::RETRY_AFTER;
 use actix_web::http::StatusCode;
+use serde::Serialize;
 use thiserror::Error;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::CONFIG;
 
 #[derive(Debug, Error)]
 pub enum ShortyError {
@@ -11,32 +18,288 @@ pub enum ShortyError {
 	LinkExceedsMaxLength,
 	#[error("Custom ID exceeds maximum length allowed.")]
 	CustomIDExceedsMaxLength,
+	#[error("The ID '{0}' is reserved for the server.")]
+	ReservedID(String),
 	#[error("Link is empty.")]
 	LinkEmpty,
+	#[error("Link is not a valid URL: {0}")]
+	InvalidLink(String),
+	#[error("Links with the scheme '{0}' are not allowed.")]
+	DisallowedScheme(String),
+	#[error("Links to '{0}' are blocked.")]
+	DomainBlocked(String),
+	#[error("Links to '{0}' are not allowed, only links to approved domains are.")]
+	DomainNotAllowed(String),
 	#[error("Maximum retries to generate a random link ID were exceeded.")]
 	RandomIDMaxRetriesExceeded,
 	#[error("An already expired Link was provided.")]
 	ExpiredLinkProvided,
+	#[error("Link with provided ID doesn't exist or is expired.")]
+	LinkNotFound,
+	#[error("A management token is required to manage this link.")]
+	ManagementTokenMissing,
+	#[error("The provided management token is invalid.")]
+	InvalidManagementToken,
+	#[error("This link is protected by a password.")]
+	PasswordRequired,
+	#[error("The provided password is incorrect.")]
+	InvalidPassword,
+	#[error("An admin token is required to use the admin API.")]
+	AdminTokenMissing,
+	#[error("The provided admin token is invalid.")]
+	InvalidAdminToken,
+	#[error("Too many requests, try again in {retry_after} seconds.")]
+	RateLimited { retry_after: u64 },
+	#[error("JSON payload exceeds maximum size allowed.")]
+	JsonExceedsMaxSize,
+	#[error("JSON payload is malformed: {0}")]
+	MalformedJson(String),
+	#[error("Query string is invalid: {0}")]
+	InvalidQuery(String),
+	#[error("Import payload couldn't be read: {0}")]
+	InvalidImport(String),
 	#[error(transparent)]
 	Database(#[from] sqlx::Error),
 	#[error(transparent)]
+	Migration(#[from] sqlx::migrate::MigrateError),
+	#[error("Failed to hash password: {0}")]
+	PasswordHash(argon2::password_hash::Error),
+	#[error("TLS setup failed: {0}")]
+	Tls(String),
+	#[error("Config is invalid: {0}")]
+	Config(String),
+	#[error("Failed to load the domain lists: {0}")]
+	DomainList(String),
+	#[error(transparent)]
 	Dotenvy(#[from] dotenvy::Error),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+}
+
+/// Stable, machine readable identifier for every kind of error the API can return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+	LinkConflict,
+	LinkExceedsMaxLength,
+	CustomIdExceedsMaxLength,
+	ReservedId,
+	LinkEmpty,
+	InvalidLink,
+	DisallowedScheme,
+	DomainBlocked,
+	DomainNotAllowed,
+	RandomIdMaxRetriesExceeded,
+	ExpiredLinkProvided,
+	LinkNotFound,
+	ManagementTokenMissing,
+	InvalidManagementToken,
+	PasswordRequired,
+	InvalidPassword,
+	AdminTokenMissing,
+	InvalidAdminToken,
+	RateLimited,
+	JsonExceedsMaxSize,
+	MalformedJson,
+	InvalidQuery,
+	InvalidImport,
+	Internal,
+}
+
+/// The JSON body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+	"code": "link_exceeds_max_length",
+	"message": "Link exceeds maximum length allowed.",
+	"field": "link",
+	"limit": 2500
+}))]
+pub struct ErrorResponse {
+	/// What went wrong.
+	pub code: ErrorCode,
+	/// Human readable description of the error.
+	pub message: String,
+	/// The request field that caused the error, if a specific one did.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub field: Option<&'static str>,
+	/// The limit that was exceeded, like the `max_link_length` for a link that is too long.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub limit: Option<usize>,
+}
+
+impl ShortyError {
+	/// The machine readable code of the error.
+	#[must_use]
+	pub fn code(&self) -> ErrorCode {
+		match self {
+			ShortyError::LinkConflict => ErrorCode::LinkConflict,
+			ShortyError::LinkExceedsMaxLength => ErrorCode::LinkExceedsMaxLength,
+			ShortyError::CustomIDExceedsMaxLength => ErrorCode::CustomIdExceedsMaxLength,
+			ShortyError::ReservedID(_) => ErrorCode::ReservedId,
+			ShortyError::LinkEmpty => ErrorCode::LinkEmpty,
+			ShortyError::InvalidLink(_) => ErrorCode::InvalidLink,
+			ShortyError::DisallowedScheme(_) => ErrorCode::DisallowedScheme,
+			ShortyError::DomainBlocked(_) => ErrorCode::DomainBlocked,
+			ShortyError::DomainNotAllowed(_) => ErrorCode::DomainNotAllowed,
+			ShortyError::RandomIDMaxRetriesExceeded => ErrorCode::RandomIdMaxRetriesExceeded,
+			ShortyError::ExpiredLinkProvided => ErrorCode::ExpiredLinkProvided,
+			ShortyError::LinkNotFound => ErrorCode::LinkNotFound,
+			ShortyError::ManagementTokenMissing => ErrorCode::ManagementTokenMissing,
+			ShortyError::InvalidManagementToken => ErrorCode::InvalidManagementToken,
+			ShortyError::PasswordRequired => ErrorCode::PasswordRequired,
+			ShortyError::InvalidPassword => ErrorCode::InvalidPassword,
+			ShortyError::AdminTokenMissing => ErrorCode::AdminTokenMissing,
+			ShortyError::InvalidAdminToken => ErrorCode::InvalidAdminToken,
+			ShortyError::RateLimited { .. } => ErrorCode::RateLimited,
+			ShortyError::JsonExceedsMaxSize => ErrorCode::JsonExceedsMaxSize,
+			ShortyError::MalformedJson(_) => ErrorCode::MalformedJson,
+			ShortyError::InvalidQuery(_) => ErrorCode::InvalidQuery,
+			ShortyError::InvalidImport(_) => ErrorCode::InvalidImport,
+			ShortyError::Database(_)
+			| ShortyError::Migration(_)
+			| ShortyError::PasswordHash(_)
+			| ShortyError::Tls(_)
+			| ShortyError::Config(_)
+			| ShortyError::DomainList(_)
+			| ShortyError::Dotenvy(_)
+			| ShortyError::Io(_) => ErrorCode::Internal,
+		}
+	}
+
+	/// The name of the variant, which identifies the kind of error in the metrics.
+	#[must_use]
+	pub fn variant(&self) -> &'static str {
+		match self {
+			ShortyError::LinkConflict => "LinkConflict",
+			ShortyError::LinkExceedsMaxLength => "LinkExceedsMaxLength",
+			ShortyError::CustomIDExceedsMaxLength => "CustomIDExceedsMaxLength",
+			ShortyError::ReservedID(_) => "ReservedID",
+			ShortyError::LinkEmpty => "LinkEmpty",
+			ShortyError::InvalidLink(_) => "InvalidLink",
+			ShortyError::DisallowedScheme(_) => "DisallowedScheme",
+			ShortyError::DomainBlocked(_) => "DomainBlocked",
+			ShortyError::DomainNotAllowed(_) => "DomainNotAllowed",
+			ShortyError::RandomIDMaxRetriesExceeded => "RandomIDMaxRetriesExceeded",
+			ShortyError::ExpiredLinkProvided => "ExpiredLinkProvided",
+			ShortyError::LinkNotFound => "LinkNotFound",
+			ShortyError::ManagementTokenMissing => "ManagementTokenMissing",
+			ShortyError::InvalidManagementToken => "InvalidManagementToken",
+			ShortyError::PasswordRequired => "PasswordRequired",
+			ShortyError::InvalidPassword => "InvalidPassword",
+			ShortyError::AdminTokenMissing => "AdminTokenMissing",
+			ShortyError::InvalidAdminToken => "InvalidAdminToken",
+			ShortyError::RateLimited { .. } => "RateLimited",
+			ShortyError::JsonExceedsMaxSize => "JsonExceedsMaxSize",
+			ShortyError::MalformedJson(_) => "MalformedJson",
+			ShortyError::InvalidQuery(_) => "InvalidQuery",
+			ShortyError::InvalidImport(_) => "InvalidImport",
+			ShortyError::Database(_) => "Database",
+			ShortyError::Migration(_) => "Migration",
+			ShortyError::PasswordHash(_) => "PasswordHash",
+			ShortyError::Tls(_) => "Tls",
+			ShortyError::Config(_) => "Config",
+			ShortyError::DomainList(_) => "DomainList",
+			ShortyError::Dotenvy(_) => "Dotenvy",
+			ShortyError::Io(_) => "Io",
+		}
+	}
+
+	/// The request field that caused the error.
+	fn field(&self) -> Option<&'static str> {
+		match self {
+			ShortyError::LinkExceedsMaxLength
+			| ShortyError::LinkEmpty
+			| ShortyError::InvalidLink(_)
+			| ShortyError::DisallowedScheme(_)
+			| ShortyError::DomainBlocked(_)
+			| ShortyError::DomainNotAllowed(_) => Some("link"),
+			ShortyError::CustomIDExceedsMaxLength
+			| ShortyError::ReservedID(_)
+			| ShortyError::LinkConflict => Some("custom_id"),
+			ShortyError::InvalidPassword => Some("password"),
+			_ => None,
+		}
+	}
+
+	/// The configured limit that was exceeded.
+	fn limit(&self) -> Option<usize> {
+		match self {
+			ShortyError::LinkExceedsMaxLength => Some(CONFIG.load().max_link_length),
+			ShortyError::CustomIDExceedsMaxLength => Some(CONFIG.load().max_custom_id_length),
+			ShortyError::JsonExceedsMaxSize => Some(CONFIG.load().max_json_size),
+			_ => None,
+		}
+	}
+}
+
+impl From<JsonPayloadError> for ShortyError {
+	fn from(err: JsonPayloadError) -> Self {
+		match err {
+			JsonPayloadError::OverflowKnownLength { .. }
+			| JsonPayloadError::Overflow { .. } => ShortyError::JsonExceedsMaxSize,
+			err => ShortyError::MalformedJson(err.to_string()),
+		}
+	}
+}
+
+impl From<&ShortyError> for ErrorResponse {
+	fn from(err: &ShortyError) -> Self {
+		let code = err.code();
+
+		// Internal errors might contain details about the server that shouldn't be exposed.
+		let message = if code == ErrorCode::Internal {
+			error!("{err}");
+			"Internal server error.".to_owned()
+		} else {
+			err.to_string()
+		};
+
+		Self {
+			code,
+			message,
+			field: err.field(),
+			limit: err.limit(),
+		}
+	}
 }
 
 impl ResponseError for ShortyError {
 	fn status_code(&self) -> StatusCode {
 		match self {
 			ShortyError::LinkConflict => StatusCode::CONFLICT,
+			ShortyError::LinkNotFound => StatusCode::NOT_FOUND,
+			ShortyError::ManagementTokenMissing
+			| ShortyError::PasswordRequired
+			| ShortyError::AdminTokenMissing => StatusCode::UNAUTHORIZED,
+			ShortyError::InvalidManagementToken
+			| ShortyError::InvalidPassword
+			| ShortyError::InvalidAdminToken => StatusCode::FORBIDDEN,
+			ShortyError::JsonExceedsMaxSize => StatusCode::PAYLOAD_TOO_LARGE,
+			ShortyError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
 			ShortyError::LinkExceedsMaxLength
 			| ShortyError::LinkEmpty
+			| ShortyError::InvalidLink(_)
+			| ShortyError::DisallowedScheme(_)
+			| ShortyError::DomainBlocked(_)
+			| ShortyError::DomainNotAllowed(_)
 			| ShortyError::ExpiredLinkProvided
+			| ShortyError::MalformedJson(_)
+			| ShortyError::InvalidQuery(_)
+			| ShortyError::InvalidImport(_)
+			| ShortyError::ReservedID(_)
 			| ShortyError::CustomIDExceedsMaxLength => StatusCode::BAD_REQUEST,
 			_ => StatusCode::INTERNAL_SERVER_ERROR,
 		}
 	}
 
 	fn error_response(&self) -> HttpResponse<BoxBody> {
-		HttpResponseBuilder::new(self.status_code())
-			.body(self.to_string())
+		let mut response = HttpResponseBuilder::new(self.status_code());
+
+		if let ShortyError::RateLimited { retry_after } = self {
+			response.insert_header((RETRY_AFTER, *retry_after));
+		}
+
+
+		response.json(ErrorResponse::from(self))
 	}
 }
@@ -0,0 +1,49 @@
+use rand::Rng;
+use serde::Deserialize;
+
+/// The characters random IDs are made of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdAlphabet {
+	/// Letters, digits, `-` and `_`, like URL safe base64.
+	#[default]
+	UrlSafe,
+	/// Letters and digits.
+	Base62,
+	/// Lowercase letters and digits, for IDs that are easy to type.
+	Lowercase,
+	/// Crockford's base32 in lowercase, without the lookalikes `i`, `l`, `o` and `u`.
+	Crockford,
+	/// Alternating consonants and vowels, like `dabopuki`, for IDs that are easy to read out loud.
+	Pronounceable,
+}
+
+const URL_SAFE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE62: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+const CROCKFORD: &[u8] = b"0123456789abcdefghjkmnpqrstvwxyz";
+const CONSONANTS: &[u8] = b"bdfghjkmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+
+impl IdAlphabet {
+	/// Generates a random ID with the provided number of characters.
+	#[must_use]
+	pub fn generate(self, length: usize) -> String {
+		let mut rng = rand::thread_rng();
+
+		(0..length)
+			.map(|i| {
+				let chars = match self {
+					Self::UrlSafe => URL_SAFE,
+					Self::Base62 => BASE62,
+					Self::Lowercase => LOWERCASE,
+					Self::Crockford => CROCKFORD,
+					Self::Pronounceable if i % 2 == 0 => CONSONANTS,
+					Self::Pronounceable => VOWELS,
+				};
+
+				char::from(chars[rng.gen_range(0..chars.len())])
+			})
+			.collect()
+	}
+}
@@ -1,20 +1,28 @@
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
 use chrono::Local;
-use serde::Deserialize;
-use sqlx::{Pool, Sqlite};
-use tracing::debug;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
 use utoipa::ToSchema;
 
-use crate::{CONFIG, ensure_http_prefix};
-use crate::error::ShortyError;
-use crate::util::{get_random_id, replace_illegal_url_chars, time_now};
+use crate::CONFIG;
+use crate::domain_filter::{DOMAIN_FILTER, host_matches_domain, host_of, normalize_domain};
+use crate::error::{ErrorCode, ShortyError};
+use crate::metrics::METRICS;
+use crate::storage::{InsertOutcome, Storage, StorageTransaction};
+use crate::transfer::{self, ConflictStrategy, Format, Importer};
+use crate::util::{escape_like_pattern, generate_management_token, insert_with_random_id, hash_management_token, hash_password, replace_illegal_url_chars, time_now, verify_password};
+use crate::validation::{check_id, normalize_link};
 
 /// This struct holds configuration options for a custom link.
-/// Optional fields are: `custom_id`, `max_uses`, and `valid_for`.
+/// Optional fields are: `custom_id`, `max_uses`, `valid_for`, `redirect_type` and `password`.
 /// `valid_for` and `max_uses` default to 0, which means essentially infinite.
 #[derive(Debug, Clone, Deserialize, ToSchema)]
-#[schema(example = json!({"link": "https://google.com", "custom_id": "search", "max_uses": 0, "valid_for": 0}))]
+#[schema(example = json!({"link": "https://google.com", "custom_id": "search", "max_uses": 0, "valid_for": 0, "redirect_type": 308, "password": "hunter2"}))]
 pub struct LinkConfig {
 	/// The link that should be shortened.
 	pub link: String,
@@ -27,28 +35,285 @@ pub struct LinkConfig {
 	/// How long the link is valid for in milliseconds.
 	#[serde(default = "default_valid_for")]
 	valid_for: i64,
+	/// The HTTP status code the link redirects with, one of 301, 302, 303, 307 or 308.
+	#[serde(default = "default_redirect_type")]
+	#[schema(value_type = u16)]
+	redirect_type: RedirectType,
+	/// Password that has to be entered before the link redirects, an empty one is ignored.
+	#[serde(default)]
+	password: Option<String>,
+}
+
+impl LinkConfig {
+	/// Creates a config for the link, settings that aren't provided fall back to the defaults of the config file.
+	#[must_use]
+	pub fn new(
+		link: String,
+		custom_id: Option<String>,
+		max_uses: Option<i64>,
+		valid_for: Option<i64>,
+		redirect_type: Option<RedirectType>,
+		password: Option<String>,
+	) -> Self {
+		Self {
+			link,
+			custom_id,
+			max_uses: max_uses.unwrap_or_else(default_max_uses),
+			valid_for: valid_for.unwrap_or_else(default_valid_for),
+			redirect_type: redirect_type.unwrap_or_else(default_redirect_type),
+			password,
+		}
+	}
+}
+
+/// This struct holds the changes the creator of a link wants to apply to it.
+/// All fields are optional, fields that aren't provided are left untouched.
+/// `valid_for` is counted from the creation of the link, not from the time of the update.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[schema(example = json!({"link": "https://duckduckgo.com", "max_uses": 10}))]
+pub struct LinkUpdate {
+	/// The new link the shortened link should redirect to.
+	pub link: Option<String>,
+	/// How often the link may be used.
+	max_uses: Option<i64>,
+	/// How long the link is valid for in milliseconds.
+	valid_for: Option<i64>,
+	/// The HTTP status code the link redirects with, one of 301, 302, 303, 307 or 308.
+	#[schema(value_type = Option<u16>)]
+	redirect_type: Option<RedirectType>,
 }
 
 /// This function exists only because serde's default can't take values or a value from a struct.
 fn default_max_uses() -> i64 {
-	CONFIG.default_max_uses
+	CONFIG.load().default_max_uses
 }
 
 /// This function exists only because serde's default can't take values or a value from a struct.
 fn default_valid_for() -> i64 {
-	CONFIG.default_valid_for
+	CONFIG.load().default_valid_for
+}
+
+/// This function exists only because serde's default can't take values or a value from a struct.
+fn default_redirect_type() -> RedirectType {
+	CONFIG.load().default_redirect_type
+}
+
+/// The kind of redirect a link answers with.
+/// It is (de)serialized as the HTTP status code and stored as such in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum RedirectType {
+	/// 301, permanent and might change the method to GET.
+	MovedPermanently,
+	/// 302, temporary and might change the method to GET.
+	Found,
+	/// 303, temporary and always changes the method to GET.
+	SeeOther,
+	/// 307, temporary and keeps the method.
+	TemporaryRedirect,
+	/// 308, permanent and keeps the method.
+	PermanentRedirect,
+}
+
+impl RedirectType {
+	#[must_use]
+	pub const fn status_code(self) -> StatusCode {
+		match self {
+			RedirectType::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+			RedirectType::Found => StatusCode::FOUND,
+			RedirectType::SeeOther => StatusCode::SEE_OTHER,
+			RedirectType::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+			RedirectType::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+		}
+	}
+}
+
+impl TryFrom<u16> for RedirectType {
+	type Error = String;
+
+	fn try_from(status: u16) -> Result<Self, Self::Error> {
+		match status {
+			301 => Ok(RedirectType::MovedPermanently),
+			302 => Ok(RedirectType::Found),
+			303 => Ok(RedirectType::SeeOther),
+			307 => Ok(RedirectType::TemporaryRedirect),
+			308 => Ok(RedirectType::PermanentRedirect),
+			status => Err(format!("{status} is not a supported redirect type, use one of 301, 302, 303, 307 or 308")),
+		}
+	}
+}
+
+impl From<RedirectType> for u16 {
+	fn from(redirect_type: RedirectType) -> Self {
+		redirect_type.status_code().as_u16()
+	}
 }
 
 /// Struct representing a (shortened) Link.
 /// All timestamps are in milliseconds.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Link {
 	pub id: String,
 	pub redirect_to: String,
-	max_uses: i64,
-	invocations: i64,
-	created_at: i64,
-	valid_for: i64,
+	pub(crate) max_uses: i64,
+	pub(crate) invocations: i64,
+	pub(crate) created_at: i64,
+	pub(crate) valid_for: i64,
+	pub(crate) management_token_hash: Option<String>,
+	pub(crate) password_hash: Option<String>,
+	/// The HTTP status code of the redirect, see [`Link::redirect_type`].
+	pub(crate) redirect_type: i64,
+	/// Whether the link is handed out to everyone who shortens its target, see [`Link::new_shared`].
+	pub(crate) shared: bool,
+}
+
+/// A freshly created link together with its management token.
+/// The token is only available right after creation, the database only stores its hash.
+/// Shared links have no management token.
+#[derive(Debug, Clone)]
+pub struct CreatedLink {
+	pub link: Link,
+	pub management_token: Option<String>,
+}
+
+/// A validated link that isn't inserted into the database yet.
+struct PendingLink {
+	link: Link,
+	management_token: Option<String>,
+	has_custom_id: bool,
+}
+
+impl PendingLink {
+	/// Inserts the link, picking a random ID for it if it has no custom one.
+	/// Links with the same ID that are expired get replaced, valid ones are a conflict.
+	async fn insert(mut self, transaction: &mut dyn StorageTransaction) -> Result<CreatedLink, ShortyError> {
+		if self.has_custom_id {
+			let outcome = transaction.insert_link_if_free(&self.link, self.link.created_at).await?;
+
+			if outcome == InsertOutcome::Occupied {
+				return Err(ShortyError::LinkConflict);
+			}
+		} else {
+			insert_with_random_id(&mut self.link, transaction).await?;
+		}
+
+
+		Ok(CreatedLink { link: self.link, management_token: self.management_token })
+	}
+}
+
+/// What happened to one link of a batch, see [`LinkStore::create_links`].
+#[derive(Debug)]
+pub enum BatchItem {
+	Created(CreatedLink),
+	Failed(ShortyError),
+	/// The link would have been created, but another link of the batch failed.
+	RolledBack,
+}
+
+/// Serializable view of a link, as returned by the API when JSON is requested.
+/// All timestamps are in milliseconds.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(example = json!({
+	"id": "search",
+	"short_url": "http://localhost:7999/search",
+	"redirect_to": "https://google.com",
+	"created_at": 1_697_500_000_000_i64,
+	"expires_at": 1_698_104_800_000_i64,
+	"max_uses": 0,
+	"invocations": 0,
+	"redirect_type": 307,
+	"password_protected": false,
+	"management_token": "B9rlbfKE3VuR_lirxI2yqAGpjU546JRhAVNX6IeMwes"
+}))]
+pub struct LinkResponse {
+	/// The ID of the link.
+	pub id: String,
+	/// The full shortened link.
+	pub short_url: String,
+	/// The link the shortened link redirects to.
+	pub redirect_to: String,
+	/// When the link was created.
+	pub created_at: i64,
+	/// When the link expires, absent if it doesn't expire based on time.
+	pub expires_at: Option<i64>,
+	/// How often the link may be used, 0 means unlimited.
+	pub max_uses: i64,
+	/// How often the link has been used.
+	pub invocations: i64,
+	/// The HTTP status code the link redirects with.
+	#[schema(value_type = u16)]
+	pub redirect_type: RedirectType,
+	/// Whether a password has to be entered before the link redirects.
+	pub password_protected: bool,
+	/// The management token of the link, only present right after creation of a link that isn't shared.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub management_token: Option<String>,
+}
+
+impl From<&Link> for LinkResponse {
+	fn from(link: &Link) -> Self {
+		Self {
+			id: link.id.clone(),
+			short_url: link.formatted(),
+			redirect_to: link.redirect_to.clone(),
+			created_at: link.created_at,
+			expires_at: link.expires_at(),
+			max_uses: link.max_uses,
+			invocations: link.invocations,
+			redirect_type: link.redirect_type(),
+			password_protected: link.is_password_protected(),
+			management_token: None,
+		}
+	}
+}
+
+impl From<&CreatedLink> for LinkResponse {
+	fn from(created: &CreatedLink) -> Self {
+		Self {
+			management_token: created.management_token.clone(),
+			..Self::from(&created.link)
+		}
+	}
+}
+
+/// Information about a link that anyone can look at before following it.
+/// Timestamps and durations are in milliseconds.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(example = json!({
+	"id": "search",
+	"short_url": "http://localhost:7999/search",
+	"redirect_to": "https://google.com",
+	"created_at": 1_697_500_000_000_i64,
+	"remaining_uses": 4,
+	"remaining_lifetime": 86_400_000
+}))]
+pub struct LinkInfo {
+	/// The ID of the link.
+	pub id: String,
+	/// The full shortened link.
+	pub short_url: String,
+	/// The link the shortened link redirects to.
+	pub redirect_to: String,
+	/// When the link was created.
+	pub created_at: i64,
+	/// How often the link can still be used, absent if there is no limit.
+	pub remaining_uses: Option<i64>,
+	/// How long the link is still valid for, absent if it doesn't expire based on time.
+	pub remaining_lifetime: Option<i64>,
+}
+
+impl From<&Link> for LinkInfo {
+	fn from(link: &Link) -> Self {
+		Self {
+			id: link.id.clone(),
+			short_url: link.formatted(),
+			redirect_to: link.redirect_to.clone(),
+			created_at: link.created_at,
+			remaining_uses: link.remaining_uses(),
+			remaining_lifetime: link.remaining_lifetime(),
+		}
+	}
 }
 
 impl Display for Link {
@@ -64,22 +329,30 @@ impl Link {
 	/// # Errors
 	///
 	/// Errors if the underlying [`Link::new_with_config`] errors.
+	#[allow(clippy::new_ret_no_self)]
 	pub async fn new(
 		link: String,
-		pool: &Pool<Sqlite>,
-	) -> Result<Self, ShortyError> {
-		let link_config = LinkConfig {
-			link,
-			custom_id: None,
-			max_uses: CONFIG.default_max_uses,
-			valid_for: CONFIG.default_valid_for,
-		};
-
+		storage: &dyn Storage,
+	) -> Result<CreatedLink, ShortyError> {
+		Link::new_with_config(Link::default_config(link), storage).await
+	}
 
-		Link::new_with_config(link_config, pool).await
+	/// Creates a new link with a default configuration that has no management token,
+	/// so it can be handed out to everyone who shortens the same link.
+	///
+	/// # Errors
+	///
+	/// Errors if the underlying [`Link::create`] errors.
+	pub async fn new_shared(
+		link: String,
+		storage: &dyn Storage,
+	) -> Result<CreatedLink, ShortyError> {
+		Link::create(Link::default_config(link), true, storage).await
 	}
 
 	/// Creates a new link according to the config provided.
+	/// A management token is generated alongside it, which allows the creator to edit
+	/// or delete the link later on.
 	///
 	/// # Errors
 	///
@@ -87,72 +360,281 @@ impl Link {
 	/// Also returns an error if there was a problem executing the SQL queries.
 	pub async fn new_with_config(
 		link_config: LinkConfig,
-		pool: &Pool<Sqlite>,
-	) -> Result<Self, ShortyError> {
-		let id = if let Some(id) = link_config.custom_id {
-			if id.len() > CONFIG.max_custom_id_length {
-				return Err(ShortyError::CustomIDExceedsMaxLength);
-			}
+		storage: &dyn Storage,
+	) -> Result<CreatedLink, ShortyError> {
+		Link::create(link_config, false, storage).await
+	}
 
-			replace_illegal_url_chars(&id)
-		} else {
-			get_random_id(pool).await?
-		};
-		let redirect_to = link_config.link;
-		let max_uses = link_config.max_uses;
-		let invocations = 0;
-		let created_at = time_now();
-		let valid_for = link_config.valid_for;
+	fn default_config(link: String) -> LinkConfig {
+		let config = CONFIG.load();
 
-		if redirect_to.is_empty() {
-			return Err(ShortyError::LinkEmpty);
-		}
 
-		if redirect_to.len() > CONFIG.max_link_length {
-			return Err(ShortyError::LinkExceedsMaxLength);
+		LinkConfig {
+			link,
+			custom_id: None,
+			max_uses: config.default_max_uses,
+			valid_for: config.default_valid_for,
+			redirect_type: config.default_redirect_type,
+			password: None,
 		}
+	}
 
-		let redirect_to = ensure_http_prefix(redirect_to);
+	/// Creates a new link according to the config provided.
+	/// Links that aren't `shared` get a management token.
+	async fn create(
+		link_config: LinkConfig,
+		shared: bool,
+		storage: &dyn Storage,
+	) -> Result<CreatedLink, ShortyError> {
+		let pending = Link::prepare(link_config, shared)?;
 
-		// If a link with the same ID exists already, return a conflict error.
-		if let Some(link) = Link::from_id_no_invocation(id.as_str(), pool).await? {
-			if !link.is_expired() {
-				return Err(ShortyError::LinkConflict);
-			}
-		}
+		let mut transaction = storage.begin().await?;
+		let created = pending.insert(transaction.as_mut()).await?;
+		transaction.commit().await?;
+
+
+		Ok(created)
+	}
+
+	/// Validates the config and builds the link from it, without inserting it yet.
+	fn prepare(link_config: LinkConfig, shared: bool) -> Result<PendingLink, ShortyError> {
+		let custom_id = link_config.custom_id
+			.map(|id| Link::validate_custom_id(id.as_str()))
+			.transpose()?;
+		let redirect_to = Link::validate_redirect(link_config.link.as_str())?;
+		let max_uses = link_config.max_uses;
+		let invocations = 0;
+		let created_at = time_now();
+		let valid_for = link_config.valid_for;
+		let management_token = (!shared).then(generate_management_token);
+		let management_token_hash = management_token.as_deref().map(hash_management_token);
+		let password_hash = link_config.password
+			.filter(|password| !password.is_empty())
+			.map(|password| hash_password(password.as_str()))
+			.transpose()?;
 
 		let shortened = Self {
-			id,
+			// Random IDs are only picked when the link gets inserted.
+			id: custom_id.clone().unwrap_or_default(),
 			redirect_to,
 			max_uses,
 			invocations,
 			created_at,
 			valid_for,
+			management_token_hash,
+			password_hash,
+			redirect_type: i64::from(u16::from(link_config.redirect_type)),
+			shared,
 		};
 
 		if shortened.is_expired() {
 			return Err(ShortyError::ExpiredLinkProvided);
 		}
 
-		// We checked if the link exists already and is valid.
-		// If it exists it has to be stale and can be replaced.
-		sqlx::query!(
-			r#"
-				INSERT OR REPLACE INTO links
-				VALUES ($1, $2, $3, $4, $5, $6)
-			"#,
-			shortened.id,
-			shortened.redirect_to,
-			max_uses,
-			invocations,
-			created_at,
-			valid_for
+
+
+		Ok(PendingLink {
+			link: shortened,
+			management_token,
+			has_custom_id: custom_id.is_some(),
+		})
+	}
+
+	/// Checks that a custom ID doesn't exceed the maximum length and isn't reserved, see [`check_id`].
+	/// Returns the ID with the chars that can't be used in URLs replaced, see [`replace_illegal_url_chars`].
+	pub(crate) fn validate_custom_id(id: &str) -> Result<String, ShortyError> {
+		if id.len() > CONFIG.load().max_custom_id_length {
+			return Err(ShortyError::CustomIDExceedsMaxLength);
+		}
+
+		let id = replace_illegal_url_chars(id);
+		check_id(id.as_str())?;
+
+
+		Ok(id)
+	}
+
+	/// Checks that the link isn't empty, is a valid URL, doesn't exceed the maximum length
+	/// and redirects to a domain that is allowed.
+	/// Returns the normalized link, see [`normalize_link`].
+	pub(crate) fn validate_redirect(redirect_to: &str) -> Result<String, ShortyError> {
+		if redirect_to.trim().is_empty() {
+			return Err(ShortyError::LinkEmpty);
+		}
+
+		if redirect_to.len() > CONFIG.load().max_link_length {
+			return Err(ShortyError::LinkExceedsMaxLength);
+		}
+
+		// Normalizing can make the link longer, like for hosts that get converted to punycode.
+		let redirect_to = normalize_link(redirect_to)?;
+
+		if redirect_to.len() > CONFIG.load().max_link_length {
+			return Err(ShortyError::LinkExceedsMaxLength);
+		}
+
+		DOMAIN_FILTER.check(redirect_to.as_str())?;
+
+
+		Ok(redirect_to)
+	}
+
+	/// Applies the changes from the update to the link and writes them to the database.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the new link is invalid or the update would expire the link.
+	/// Also returns an error if there was a problem executing the SQL queries.
+	pub async fn update(
+		mut self,
+		update: LinkUpdate,
+		storage: &dyn Storage,
+	) -> Result<Self, ShortyError> {
+		if let Some(redirect_to) = update.link {
+			self.redirect_to = Link::validate_redirect(redirect_to.as_str())?;
+		}
+
+		if let Some(max_uses) = update.max_uses {
+			self.max_uses = max_uses;
+		}
+
+		if let Some(valid_for) = update.valid_for {
+			self.valid_for = valid_for;
+		}
+
+		if let Some(redirect_type) = update.redirect_type {
+			self.redirect_type = i64::from(u16::from(redirect_type));
+		}
+
+		// Revoking a link is done by deleting it, not by updating it into an expired state.
+		if self.is_expired() {
+			return Err(ShortyError::ExpiredLinkProvided);
+		}
+
+		storage.update_link(&self).await?;
+
+
+		Ok(self)
+	}
+
+	/// Deletes the link from the database.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn delete(self, storage: &dyn Storage) -> Result<(), ShortyError> {
+		storage.delete_link(self.id.as_str()).await
+	}
+
+	/// Expires the link immediately, it gets removed on the next clean up.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn expire(mut self, storage: &dyn Storage) -> Result<Self, ShortyError> {
+		// A negative valid_for always counts as expired, see [`Link::is_expired`].
+		self.valid_for = -1;
+
+		storage.update_link(&self).await?;
+
+
+		Ok(self)
+	}
+
+	/// Retrieves a page of links whose ID or target contain the search term, newest first.
+	/// If no search term is provided, all links are considered.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn search(
+		search: Option<&str>,
+		limit: i64,
+		offset: i64,
+		storage: &dyn Storage,
+	) -> Result<Vec<Self>, ShortyError> {
+		// Without a search term the pattern matches everything.
+		let pattern = format!("%{}%", escape_like_pattern(search.unwrap_or_default()));
+
+		let links = storage.search_links(pattern.as_str(), limit, offset).await?;
+
+
+		Ok(links)
+	}
+
+	/// Counts the links whose ID or target contain the search term.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn count(search: Option<&str>, storage: &dyn Storage) -> Result<i64, ShortyError> {
+		// Without a search term the pattern matches everything.
+		let pattern = format!("%{}%", escape_like_pattern(search.unwrap_or_default()));
+
+
+		storage.count_links(pattern.as_str()).await
+	}
+
+	/// Retrieves all links that redirect to the domain or one of its subdomains.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn by_domain(domain: &str, storage: &dyn Storage) -> Result<Vec<Self>, ShortyError> {
+		// Hosts are normalized when links are created, so the domain has to be normalized the same way.
+		let Ok(domain) = normalize_domain(domain) else {
+			debug!("No links can redirect to the invalid domain '{domain}'");
+			return Ok(Vec::new());
+		};
+
+		// The LIKE only narrows down the candidates, the hosts are compared properly afterwards.
+		let pattern = format!("%{}%", escape_like_pattern(domain.as_str()));
+
+		let candidates = storage.links_to(pattern.as_str()).await?;
+
+
+		Ok(
+			candidates.into_iter()
+				.filter(|link| {
+					host_of(link.redirect_to.as_str())
+						.is_some_and(|host| host_matches_domain(host.as_str(), domain.as_str()))
+				})
+				.collect()
 		)
-			.execute(pool)
-			.await?;
+	}
+
+	/// Checks if the provided token is the management token of this link.
+	/// Links created before management tokens were introduced can't be managed.
+	#[must_use]
+	pub fn management_token_matches(&self, token: &str) -> bool {
+		self.management_token_hash
+			.as_deref()
+			.is_some_and(|hash| hash == hash_management_token(token))
+	}
+
+	/// The kind of redirect the link answers with.
+	/// Falls back to the configured default if the database contains an unsupported status code.
+	#[must_use]
+	pub fn redirect_type(&self) -> RedirectType {
+		u16::try_from(self.redirect_type)
+			.ok()
+			.and_then(|status| RedirectType::try_from(status).ok())
+			.unwrap_or(CONFIG.load().default_redirect_type)
+	}
 
+	/// Whether a password has to be entered before the link redirects.
+	#[must_use]
+	pub fn is_password_protected(&self) -> bool {
+		self.password_hash.is_some()
+	}
 
-		Ok(shortened)
+	/// Checks if the provided password unlocks this link.
+	/// Links without a password can't be unlocked, they redirect right away.
+	#[must_use]
+	pub fn password_matches(&self, password: &str) -> bool {
+		self.password_hash
+			.as_deref()
+			.is_some_and(|hash| verify_password(password, hash))
 	}
 
 	/// A link with a valid_for of 0 is considered non-expiring based on time.
@@ -171,104 +653,159 @@ impl Link {
 		time_expired || uses_invalid
 	}
 
-	/// Retrieves a link from the database, if it exists.
-	/// Calling this function also increments the invocations if the link exists in the database.
-	async fn from_id(id: &str, pool: &Pool<Sqlite>) -> Result<Option<Self>, ShortyError> {
-		let link = sqlx::query_as!(
-			Self,
-			r#"
-			SELECT * FROM links
-			WHERE id = $1;
-			UPDATE links
-			SET invocations = invocations + 1
-			WHERE id = $2;
-			"#,
-			id,
-			id
-		)
-			.fetch_optional(pool)
-			.await?;
-
-
-		Ok(link)
+	/// The point in time after which the link is expired.
+	/// Returns `None` if the link doesn't expire based on time.
+	#[must_use]
+	pub fn expires_at(&self) -> Option<i64> {
+		(self.valid_for != 0).then_some(self.created_at + self.valid_for)
 	}
 
-	/// Retrieves a link from the database, if it exists.
-	/// This function **does not** increment the invocation counter of a link.
-	async fn from_id_no_invocation(id: &str, pool: &Pool<Sqlite>) -> Result<Option<Self>, ShortyError> {
-		let link = sqlx::query_as!(
-			Self,
-			r#"
-			SELECT * FROM links
-			WHERE id = $1;
-			"#,
-			id,
-		)
-			.fetch_optional(pool)
-			.await?;
-
-
-		Ok(link)
+	/// How often the link can still be used.
+	/// Returns `None` if the link can be used infinitely.
+	#[must_use]
+	pub fn remaining_uses(&self) -> Option<i64> {
+		(self.max_uses != 0).then(|| (self.max_uses - self.invocations).max(0))
 	}
 
-	/// Checks if the link exists in the database.
-	///
-	/// # Errors
-	///
-	/// Errors if there is some problem communicating with the database.
-	pub async fn link_exists(id: &str, pool: &Pool<Sqlite>) -> Result<bool, ShortyError> {
-		let link_row = sqlx::query!(r#"
-			SELECT id FROM links WHERE id = ?;
-		"#,
-		id
-		)
-			.fetch_optional(pool)
-			.await?;
-
-
-		Ok(link_row.is_some())
+	/// How many milliseconds the link is still valid for.
+	/// Returns `None` if the link doesn't expire based on time.
+	#[must_use]
+	pub fn remaining_lifetime(&self) -> Option<i64> {
+		self.expires_at().map(|expires_at| (expires_at - time_now()).max(0))
 	}
 
 	/// Formats self, according to the options set in the config file.
 	#[must_use]
 	pub fn formatted(&self) -> String {
-		format!("{}/{}", CONFIG.public_url, self.id)
+		format!("{}/{}", CONFIG.load().public_url, self.id)
 	}
 }
 
 pub struct LinkStore {
-	db: Pool<Sqlite>,
+	storage: Arc<dyn Storage>,
 }
 
 impl LinkStore {
 	#[must_use]
-	pub fn new(db: Pool<Sqlite>) -> Self {
-		Self { db }
+	pub fn new(storage: Arc<dyn Storage>) -> Self {
+		Self { storage }
 	}
 
-	/// Retrieves a link with the provided ID, if it exists.
+	/// Retrieves a link with the provided ID and counts the use, if it exists and isn't expired.
+	/// The check and the count happen atomically, so a link is never used more than `max_uses` times.
+	/// Password protected links aren't retrieved, they have to be unlocked with [`LinkStore::unlock`].
+	/// Links to domains that got blocked after they were created aren't retrieved either, and no use is counted for them.
 	pub async fn get(&self, id: &str) -> Option<Link> {
-		let link = Link::from_id(id, &self.db).await;
-
-		if let Ok(Some(link)) = link {
-			if !link.is_expired() {
-				return Some(link);
-			}
+		// The domain is checked before the use is counted, so refused requests don't use up the link.
+		match self.storage.get_link(id).await {
+			Ok(Some(link)) if !DOMAIN_FILTER.allows(link.redirect_to.as_str()) => {
+				debug!("{id} got requested but redirects to a domain that isn't allowed.");
+				return None;
+			},
+			Ok(Some(_)) => {},
+			Ok(None) => {
+				debug!("{id} got requested but doesn't exist.");
+				return None;
+			},
+			Err(why) => {
+				error!("{why}");
+				return None;
+			},
+		}
 
-			debug!("{} got requested but is expired.", link.id);
+		match self.storage.use_link(id, time_now(), false).await {
+			Ok(Some(link)) => Some(link),
+			Ok(None) => {
+				debug!("{id} got requested but is expired or password protected.");
+				None
+			},
+			Err(why) => {
+				error!("{why}");
+				None
+			},
 		}
+	}
+
+	/// Retrieves the password protected link with the provided ID without counting it as an invocation.
+	/// Returns `None` if the link doesn't exist, is expired, isn't password protected
+	/// or redirects to a domain that isn't allowed.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn locked(&self, id: &str) -> Result<Option<Link>, ShortyError> {
+		let link = self.storage
+			.get_link(id)
+			.await?
+			.filter(|link| {
+				link.is_password_protected()
+					&& !link.is_expired()
+					&& DOMAIN_FILTER.allows(link.redirect_to.as_str())
+			});
+
+
+		Ok(link)
+	}
+
+	/// Retrieves the password protected link with the provided ID and counts the use,
+	/// if the password matches.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the link doesn't exist, is expired, has no password or the password doesn't match.
+	/// Also errors if there is some problem communicating with the database.
+	pub async fn unlock(&self, id: &str, password: &str) -> Result<Link, ShortyError> {
+		let link = self.locked(id).await?.ok_or(ShortyError::LinkNotFound)?;
 
+		if !link.password_matches(password) {
+			return Err(ShortyError::InvalidPassword);
+		}
 
-		None
+		// The link might have been used up in the meantime, so the use is counted conditionally.
+		self.storage
+			.use_link(id, time_now(), true)
+			.await?
+			.ok_or(ShortyError::LinkNotFound)
 	}
 
 	/// Creates a shortened link with default settings.
+	/// If `deduplicate_links` is enabled, everyone who shortens the same link gets the same shared link,
+	/// which has no management token. Shortening a link concurrently might still create two of them.
 	///
 	/// # Errors
 	///
-	/// Returns an error if the underlying [`Link::new`] call fails.
-	pub async fn create_link(&self, link: String) -> Result<Link, ShortyError> {
-		Link::new(link, &self.db).await
+	/// Returns an error if the underlying [`Link::new`] or [`Link::new_shared`] call fails.
+	pub async fn create_link(&self, link: String) -> Result<CreatedLink, ShortyError> {
+		let config = CONFIG.load();
+
+		// Links with limited uses would be used up by everyone sharing them.
+		if !config.deduplicate_links || config.default_max_uses != 0 {
+			let created = Link::new(link, &*self.storage).await?;
+			METRICS.links_created(1);
+
+			return Ok(created);
+		}
+
+		let redirect_to = Link::validate_redirect(link.as_str())?;
+		let shared = self.storage
+			.find_shared_link(
+				redirect_to.as_str(),
+				config.default_valid_for,
+				i64::from(u16::from(config.default_redirect_type)),
+				time_now(),
+			)
+			.await?;
+
+		if let Some(link) = shared {
+			debug!("Reusing {} for {redirect_to}", link.id);
+			return Ok(CreatedLink { link, management_token: None });
+		}
+
+		let created = Link::new_shared(redirect_to, &*self.storage).await?;
+		METRICS.links_created(1);
+
+
+		Ok(created)
 	}
 
 	/// Creates a shortened link with custom settings.
@@ -279,40 +816,307 @@ impl LinkStore {
 	pub async fn create_link_with_config(
 		&self,
 		link_config: LinkConfig,
+	) -> Result<CreatedLink, ShortyError> {
+		let created = Link::new_with_config(link_config, &*self.storage).await?;
+		METRICS.links_created(1);
+
+
+		Ok(created)
+	}
+
+	/// Creates several shortened links with custom settings in a single transaction.
+	/// Every link gets created or fails on its own, the results are in the same order as the configs.
+	/// If `all_or_nothing` is set and any of them fails, none are created.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database, none of the links are created then.
+	pub async fn create_links(
+		&self,
+		link_configs: Vec<LinkConfig>,
+		all_or_nothing: bool,
+	) -> Result<Vec<BatchItem>, ShortyError> {
+		// Hashing the passwords takes a while, so it's done before the transaction holds up the database.
+		let pending_links: Vec<_> = link_configs.into_iter()
+			.map(|link_config| Link::prepare(link_config, false))
+			.collect();
+
+		let mut transaction = self.storage.begin().await?;
+		let mut items = Vec::with_capacity(pending_links.len());
+
+		for pending in pending_links {
+			let created = match pending {
+				Ok(pending) => pending.insert(transaction.as_mut()).await,
+				Err(why) => Err(why),
+			};
+
+			match created {
+				Ok(created) => items.push(BatchItem::Created(created)),
+				// Problems of the server aren't the fault of the link, they fail the whole batch.
+				Err(why) if why.code() == ErrorCode::Internal => return Err(why),
+				Err(why) => items.push(BatchItem::Failed(why)),
+			}
+		}
+
+		if all_or_nothing && items.iter().any(|item| matches!(item, BatchItem::Failed(_))) {
+			// Dropping the transaction discards the links that were inserted already.
+			drop(transaction);
+
+			return Ok(
+				items.into_iter()
+					.map(|item| match item {
+						BatchItem::Created(_) => BatchItem::RolledBack,
+						item => item,
+					})
+					.collect()
+			);
+		}
+
+		transaction.commit().await?;
+
+		let created = items.iter().filter(|item| matches!(item, BatchItem::Created(_))).count();
+		METRICS.links_created(created as u64);
+
+
+		Ok(items)
+	}
+
+	/// Updates the link with the provided ID, if the management token matches.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the link doesn't exist, the token doesn't match or
+	/// the underlying [`Link::update`] call fails.
+	pub async fn update_link(
+		&self,
+		id: &str,
+		token: &str,
+		update: LinkUpdate,
 	) -> Result<Link, ShortyError> {
-		Link::new_with_config(link_config, &self.db).await
+		let link = self.get_managed(id, token).await?;
+
+
+		link.update(update, &*self.storage).await
+	}
+
+	/// Deletes the link with the provided ID, if the management token matches.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the link doesn't exist, the token doesn't match or
+	/// the underlying [`Link::delete`] call fails.
+	pub async fn delete_link(&self, id: &str, token: &str) -> Result<(), ShortyError> {
+		let link = self.get_managed(id, token).await?;
+
+
+		link.delete(&*self.storage).await
+	}
+
+	/// Retrieves a link with the provided ID without counting it as an invocation.
+	/// Expired links are treated as if they didn't exist.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the link doesn't exist or is expired.
+	/// Also errors if there is some problem communicating with the database.
+	pub async fn inspect(&self, id: &str) -> Result<Link, ShortyError> {
+		self.storage.get_link(id)
+			.await?
+			.filter(|link| !link.is_expired())
+			.ok_or(ShortyError::LinkNotFound)
+	}
+
+	/// Retrieves a link for management purposes without counting it as an invocation.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the link doesn't exist or the token doesn't match.
+	/// Also errors if there is some problem communicating with the database.
+	pub async fn get_managed(&self, id: &str, token: &str) -> Result<Link, ShortyError> {
+		let link = self.inspect(id).await?;
+
+		if !link.management_token_matches(token) {
+			return Err(ShortyError::InvalidManagementToken);
+		}
+
+
+		Ok(link)
+	}
+
+	/// Retrieves a page of links for the admin API, see [`Link::search`].
+	/// Returns the links along with the total amount of links matching the search.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn list(
+		&self,
+		search: Option<&str>,
+		limit: i64,
+		offset: i64,
+	) -> Result<(Vec<Link>, i64), ShortyError> {
+		let links = Link::search(search, limit, offset, &*self.storage).await?;
+		let total = Link::count(search, &*self.storage).await?;
+
+
+		Ok((links, total))
+	}
+
+	/// Expires the link with the provided ID, regardless of its management token.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the link doesn't exist or the underlying [`Link::expire`] call fails.
+	pub async fn force_expire(&self, id: &str) -> Result<Link, ShortyError> {
+		let link = self.storage.get_link(id)
+			.await?
+			.ok_or(ShortyError::LinkNotFound)?;
+
+
+		link.expire(&*self.storage).await
+	}
+
+	/// Deletes the link with the provided ID, regardless of its management token.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the link doesn't exist or the underlying [`Link::delete`] call fails.
+	pub async fn force_delete(&self, id: &str) -> Result<(), ShortyError> {
+		let link = self.storage.get_link(id)
+			.await?
+			.ok_or(ShortyError::LinkNotFound)?;
+
+
+		link.delete(&*self.storage).await
+	}
+
+	/// Deletes all links that redirect to the domain or one of its subdomains.
+	/// Returns the IDs of the deleted links.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn purge_domain(&self, domain: &str) -> Result<Vec<String>, ShortyError> {
+		let links = Link::by_domain(domain, &*self.storage).await?;
+		let mut ids = Vec::with_capacity(links.len());
+
+		for link in links {
+			ids.push(link.id.clone());
+			link.delete(&*self.storage).await?;
+		}
+
+		debug!("Purged {} links to {domain}", ids.len());
+
+
+		Ok(ids)
+	}
+
+	/// Streams all links in the format, see [`transfer::export`].
+	pub fn export(&self, format: Format) -> impl Stream<Item = Result<Bytes, ShortyError>> {
+		transfer::export(Arc::clone(&self.storage), format)
+	}
+
+	/// Creates an importer that inserts links into this store, see [`Importer::import`].
+	#[must_use]
+	pub fn importer(&self, conflict: ConflictStrategy) -> Importer {
+		Importer::new(Arc::clone(&self.storage), conflict)
 	}
 
 	/// This function deletes stale links from the database.
+	/// Returns how many links were deleted.
 	///
 	/// # Errors
 	///
 	/// Errors if theres a problem executing the SQL queries.
-	pub async fn clean(&self) -> Result<(), ShortyError> {
+	pub async fn clean(&self) -> Result<u64, ShortyError> {
 		debug!("Clearing stale links");
 
-		let res = sqlx::query!("SELECT COUNT(*) AS num_before FROM links").fetch_one(&self.db).await?;
-		let num_before = res.num_before;
-
-		let now = time_now();
-		sqlx::query!(
-			r#"
-			DELETE FROM links
-			WHERE max_uses != 0 AND invocations > max_uses
-			OR created_at + valid_for < $1
-			"#,
-			now
-		)
-			.execute(&self.db)
-			.await?;
+		let removed = self.storage.delete_stale_links(time_now()).await?;
+		debug!("Removed elements: {removed}");
+		METRICS.links_cleaned(removed);
+
+
+		Ok(removed)
+	}
+
+	/// All metrics in the Prometheus text format, along with the amount of links and the database connections.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn metrics(&self) -> Result<String, ShortyError> {
+		let total = Link::count(None, &*self.storage).await?;
+
+
+		Ok(METRICS.render(total, self.storage.pool_usage()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use crate::error::ShortyError;
+	use crate::storage::sqlite::SqliteStorage;
+	use crate::storage::Storage;
+
+	use super::{BatchItem, LinkConfig, LinkStore};
+
+	fn link_config(link: &str, custom_id: Option<&str>) -> LinkConfig {
+		LinkConfig::new(link.to_owned(), custom_id.map(str::to_owned), None, None, None, None)
+	}
+
+	async fn link_store() -> (LinkStore, Arc<dyn Storage>) {
+		let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::in_memory().await.unwrap());
+		storage.migrate().await.unwrap();
+
+
+		(LinkStore::new(storage.clone()), storage)
+	}
+
+	#[actix_web::test]
+	async fn batch_items_fail_on_their_own() {
+		let (links, storage) = link_store().await;
+		links.create_link_with_config(link_config("https://example.com/taken", Some("taken"))).await.unwrap();
+
+		let items = links.create_links(vec![
+			link_config("https://example.com/a", Some("batch-a")),
+			link_config("https://example.com/b", Some("taken")),
+			link_config("https://example.com/c", Some("admin")),
+			link_config("", None),
+			link_config("https://example.com/d", None),
+		], false).await.unwrap();
+
+		assert!(matches!(&items[0], BatchItem::Created(created) if created.link.id == "batch-a"));
+		assert!(matches!(items[1], BatchItem::Failed(ShortyError::LinkConflict)));
+		assert!(matches!(items[2], BatchItem::Failed(ShortyError::ReservedID(_))));
+		assert!(matches!(items[3], BatchItem::Failed(ShortyError::LinkEmpty)));
+		let BatchItem::Created(random) = &items[4] else {
+			panic!("The link with a random ID wasn't created: {:?}", items[4]);
+		};
+
+		assert!(storage.get_link("batch-a").await.unwrap().is_some());
+		assert!(storage.get_link(random.link.id.as_str()).await.unwrap().is_some());
+		let taken = storage.get_link("taken").await.unwrap().unwrap();
+		assert_eq!(taken.redirect_to, "https://example.com/taken");
+	}
 
-		let res = sqlx::query!("SELECT COUNT(*) AS num_after FROM links").fetch_one(&self.db).await?;
-		let num_after = res.num_after;
+	#[actix_web::test]
+	async fn failed_batch_item_rolls_back_all_or_nothing() {
+		let (links, storage) = link_store().await;
 
-		let delta = num_before - num_after;
-		debug!("Size before cleaning: {num_before}. After cleaning: {num_after}. Removed elements: {delta}");
+		let items = links.create_links(vec![
+			link_config("https://example.com/a", Some("batch-a")),
+			link_config("https://example.com/b", Some("admin")),
+			link_config("https://example.com/c", Some("batch-c")),
+		], true).await.unwrap();
 
+		assert!(matches!(items[0], BatchItem::RolledBack));
+		assert!(matches!(items[1], BatchItem::Failed(ShortyError::ReservedID(_))));
+		assert!(matches!(items[2], BatchItem::RolledBack));
 
-		Ok(())
+		assert!(storage.get_link("batch-a").await.unwrap().is_none());
+		assert!(storage.get_link("batch-c").await.unwrap().is_none());
+		assert!(storage.links_after("", 10).await.unwrap().is_empty());
 	}
 }
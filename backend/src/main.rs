@@ -1,110 +1,125 @@
 #![allow(clippy::module_name_repetitions)]
 #![allow(clippy::module_inception)]
 
-use std::io::{Read, Write};
 use std::path::Path;
 use std::time::Duration;
 
 use actix_cors::Cors;
 use actix_web::{App, HttpServer, web};
+use actix_web::http::header;
+use actix_web::middleware::from_fn;
+use clap::Parser;
 use lazy_static::lazy_static;
-use sqlx::migrate::MigrateDatabase;
-use sqlx::Sqlite;
-use sqlx::sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
-use tracing::{debug, error, info, Level};
+use tracing::{debug, error, info, Level, warn};
 use tracing_subscriber::EnvFilter;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use crate::config::Config;
-use crate::config::SAMPLE_CONFIG;
-use crate::endpoints::{ApiDoc, create_shortened, create_shortened_custom, get_config, get_favicon, get_shortened, index, serve_file};
+use crate::cli::{Cli, Command};
+use crate::config::{Config, SharedConfig};
+use crate::endpoints::{ApiDoc, create_shortened, create_shortened_batch, create_shortened_custom, delete_managed, get_config, get_favicon, get_info, get_metrics, get_shortened, get_stats, healthz, index, MANAGEMENT_TOKEN_HEADER, preview, readyz, serve_file, unlock, update_managed};
+use crate::domain_filter::DOMAIN_FILTER;
 use crate::error::ShortyError;
 use crate::link::{LinkConfig, LinkStore};
-use crate::util::ensure_http_prefix;
+use crate::storage::Storage;
+use crate::tls::CertificateResolver;
+use crate::visit::{open_geoip_database, VisitStore};
 
+pub mod admin;
+pub mod cli;
 pub mod util;
 pub mod link;
+pub mod metrics;
+pub mod rate_limit;
 pub mod config;
+pub mod domain_filter;
 pub mod error;
+pub mod id_generator;
 pub mod endpoints;
+pub mod storage;
+pub mod tls;
+pub mod transfer;
+pub mod validation;
+pub mod visit;
 
 const CLEAN_SLEEP_DURATION: Duration = Duration::from_secs(60 * 60);
 
 lazy_static! {
-	static ref CONFIG: Config = {
-		let config_location = std::env::var("SHORTY_CONFIG")
-			.unwrap_or_else(|_| "./config.toml".to_owned());
-		let path = Path::new(&config_location);
-
-		if !path.exists() {
-			let mut file = std::fs::File::create(path).expect("Failed to create sample config file");
-			file.write_all(SAMPLE_CONFIG.as_bytes()).expect("Couldn't write the sample config file");
-
-			error!(
-				"You have to configure the config file. A sample config was created at {}",
-				config_location
-			);
-			std::process::exit(1);
-		}
-
-		let mut file = std::fs::File::open(path).expect("Failed to open config file.");
-		let mut content = String::new();
-		file.read_to_string(&mut content).expect("Failed to read config file.");
-
-
-		Config::new(content.as_str()).expect("Failed to parse config")
-	};
+	static ref CONFIG: SharedConfig = SharedConfig::new(Config::load().unwrap_or_else(|why| {
+		error!("{why}");
+		std::process::exit(1);
+	}));
 }
 
 #[tokio::main]
 async fn main() -> Result<(), ShortyError> {
+	let cli = Cli::parse();
+	let command = cli.command.unwrap_or(Command::Serve);
+
 	if Path::new(".env").exists() {
 		dotenvy::dotenv()?;
 	}
 
-	let env_filter = EnvFilter::from_default_env()
-		.add_directive(Level::INFO.into())
-		.add_directive("shorty=debug".parse().unwrap());
+	cli.config.into_sources().install();
 
-	tracing_subscriber::fmt()
-		.with_env_filter(env_filter)
+	let subscriber = tracing_subscriber::fmt()
 		.with_line_number(true)
-		.with_file(true)
-		.init();
+		.with_file(true);
+
+	// The other commands write their results to stdout, so they only log problems and do that to stderr.
+	if let Command::Serve = command {
+		let env_filter = EnvFilter::from_default_env()
+			.add_directive(Level::INFO.into())
+			.add_directive("shorty=debug".parse().unwrap());
 
-	if !Sqlite::database_exists(CONFIG.database_location.as_str()).await? {
-		Sqlite::create_database(CONFIG.database_location.as_str()).await.expect("Couldn't create database file");
+		subscriber.with_env_filter(env_filter).init();
+
+		return serve().await;
 	}
 
-	let db_options = SqliteConnectOptions::new()
-		.auto_vacuum(SqliteAutoVacuum::Full)
-		.journal_mode(SqliteJournalMode::Wal)
-		.filename(CONFIG.database_location.as_str());
+	let env_filter = EnvFilter::from_default_env()
+		.add_directive(Level::WARN.into());
 
-	let pool = SqlitePoolOptions::new()
-		.max_connections(5)
-		.min_connections(1)
-		.max_lifetime(Some(Duration::from_secs(60 * 60)))
-		.connect_with(db_options)
-		.await?;
+	subscriber.with_env_filter(env_filter).with_writer(std::io::stderr).init();
 
-	sqlx::migrate!()
-		.run(&pool)
+	if let Err(why) = cli::run(command).await {
+		eprintln!("Error: {why}");
+		std::process::exit(1);
+	}
+
+
+	Ok(())
+}
+
+/// Runs the server until it is shut down.
+async fn serve() -> Result<(), ShortyError> {
+	// These keys are only read here, changing them needs a restart.
+	let config = CONFIG.load();
+	let storage = storage::connect(config.database_location.as_str()).await?;
+
+	storage.migrate()
 		.await
 		.expect("Failed db schema migration.");
 
 	// Gracefully close the database connection(s) on CTRL+C
-	let pool_clone = pool.clone();
+	let storage_clone = storage.clone();
 	tokio::task::spawn(async move {
 		tokio::signal::ctrl_c().await.expect("Error awaiting SIGINT.");
 		info!("Received SIGINT, shutting down...");
 		debug!("Closing Database pool.");
-		pool_clone.close().await;
+		storage_clone.close().await;
 		debug!("Closed Database pool.");
 	});
 
-	let links = web::Data::new(LinkStore::new(pool.clone()));
+	// Load the domain lists right away, so broken lists are noticed on startup.
+	DOMAIN_FILTER.reload()?;
+	domain_filter::reload_on_change();
+
+	#[cfg(unix)]
+	config::reload_on_sighup();
+	config::reload_on_change();
+
+	let links = web::Data::new(LinkStore::new(storage.clone()));
 	let links_clone = links.clone();
 
 	tokio::task::spawn(async move {
@@ -116,24 +131,52 @@ async fn main() -> Result<(), ShortyError> {
 		}
 	});
 
-	let pool = web::Data::new(pool);
-	info!("Starting server at {}:{}", CONFIG.listen_url, CONFIG.port);
+	let storage_data: web::Data<dyn Storage> = web::Data::from(storage.clone());
+	let visits = web::Data::new(VisitStore::new(storage, open_geoip_database()));
+
+	info!("Starting server at {}:{}", config.listen_url, config.port);
+
+	let tls = CertificateResolver::from_config()?;
 
 	let openapi = ApiDoc::openapi();
 
-	HttpServer::new(move || {
+	let metrics_port = config.metrics_port;
+	if let Some(metrics_port) = metrics_port {
+		info!("Serving metrics at {}:{metrics_port}", config.listen_url);
+
+		let metrics_links = links.clone();
+		let metrics_server = HttpServer::new(move || App::new().app_data(metrics_links.clone()).service(get_metrics))
+			.workers(1)
+			.bind((config.listen_url.as_str(), metrics_port))
+			.expect("Failed to bind metrics port or listen address.")
+			.run();
+
+		tokio::task::spawn(metrics_server);
+	}
+
+	let max_json_size = config.max_json_size;
+	let server = HttpServer::new(move || {
 		let json_config = web::JsonConfig::default()
-			.limit(CONFIG.max_json_size);
+			.limit(max_json_size)
+			.error_handler(|err, _| ShortyError::from(err).into());
+
+		let query_config = web::QueryConfig::default()
+			.error_handler(|err, _| ShortyError::InvalidQuery(err.to_string()).into());
 
 		let cors = Cors::default()
 			.allow_any_origin()
-			.allowed_methods(vec!["GET", "POST"]);
+			.allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
+			.allowed_headers(vec![header::AUTHORIZATION, header::CONTENT_TYPE])
+			.expose_headers(vec![MANAGEMENT_TOKEN_HEADER]);
 
 		App::new()
 			.wrap(cors)
+			.wrap(from_fn(metrics::track_requests))
 			.app_data(json_config)
+			.app_data(query_config)
 			.app_data(links.clone())
-			.app_data(pool.clone())
+			.app_data(visits.clone())
+			.app_data(storage_data.clone())
 			.service(
 				SwaggerUi::new("/documentation/{_:.*}").url("/documentation/openapi.json", openapi.clone())
 			)
@@ -141,11 +184,52 @@ async fn main() -> Result<(), ShortyError> {
 			.service(index)
 			.service(serve_file)
 			.service(get_favicon)
+			.service(healthz)
+			.service(readyz)
+			.configure(|cfg| {
+				if metrics_port.is_none() {
+					cfg.service(get_metrics);
+				}
+			})
+			.service(web::scope("/admin").configure(admin::configure))
+			.service(get_info)
+			.service(get_stats)
+			.service(preview)
+			.service(update_managed)
+			.service(delete_managed)
 			.service(get_shortened)
+			.service(unlock)
+			.service(create_shortened_batch)
 			.service(create_shortened_custom)
 			.service(create_shortened)
-	})
-		.bind((CONFIG.listen_url.as_str(), CONFIG.port))
+	});
+
+	let server = if let Some(resolver) = tls {
+		#[cfg(unix)]
+		tls::reload_on_sighup(resolver.clone());
+
+		if let Some(redirect_port) = config.http_redirect_port {
+			info!("Redirecting HTTP at {}:{redirect_port} to HTTPS", config.listen_url);
+
+			let redirect_server = HttpServer::new(|| App::new().default_service(web::to(tls::redirect_to_https)))
+				.workers(1)
+				.bind((config.listen_url.as_str(), redirect_port))
+				.expect("Failed to bind HTTP redirect port or listen address.")
+				.run();
+
+			tokio::task::spawn(redirect_server);
+		}
+
+		server.bind_rustls_021((config.listen_url.as_str(), config.port), resolver.server_config())
+	} else {
+		if config.http_redirect_port.is_some() {
+			warn!("Ignoring `http_redirect_port`, because HTTPS isn't enabled.");
+		}
+
+		server.bind((config.listen_url.as_str(), config.port))
+	};
+
+	server
 		.expect("Failed to bind port or listen address.")
 		.run()
 		.await
@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+
+use crate::error::ShortyError;
+use crate::storage::{InsertOutcome, PoolUsage};
+
+pub static METRICS: Metrics = Metrics::new();
+
+/// The upper bounds of the request latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counts what the server did, exported in the Prometheus text format by [`Metrics::render`].
+/// The counters start at 0 every time the server starts.
+#[derive(Debug)]
+pub struct Metrics {
+	links_created: AtomicU64,
+	redirects: AtomicU64,
+	not_found: AtomicU64,
+	links_cleaned: AtomicU64,
+	/// How many random IDs were tried, to keep an eye on how crowded the ID space gets.
+	id_attempts: AtomicU64,
+	/// How many of the tried IDs belonged to links that weren't expired.
+	id_collisions: AtomicU64,
+	/// How many of the tried IDs belonged to expired links, which were replaced.
+	id_reused_expired: AtomicU64,
+	/// How often each [`ShortyError`] variant was returned to a client, by the name of the variant.
+	errors: Mutex<BTreeMap<&'static str, u64>>,
+	latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+	latency_micros: AtomicU64,
+	requests: AtomicU64,
+}
+
+impl Metrics {
+	const fn new() -> Self {
+		// Atomics aren't `Copy`, so the array can't be written as `[AtomicU64::new(0); N]`.
+		#[allow(clippy::declare_interior_mutable_const)]
+		const ZERO: AtomicU64 = AtomicU64::new(0);
+
+		Self {
+			links_created: AtomicU64::new(0),
+			redirects: AtomicU64::new(0),
+			not_found: AtomicU64::new(0),
+			links_cleaned: AtomicU64::new(0),
+			id_attempts: AtomicU64::new(0),
+			id_collisions: AtomicU64::new(0),
+			id_reused_expired: AtomicU64::new(0),
+			errors: Mutex::new(BTreeMap::new()),
+			latency_buckets: [ZERO; LATENCY_BUCKETS.len()],
+			latency_micros: AtomicU64::new(0),
+			requests: AtomicU64::new(0),
+		}
+	}
+
+	/// Counts newly created links.
+	pub fn links_created(&self, count: u64) {
+		self.links_created.fetch_add(count, Ordering::Relaxed);
+	}
+
+	/// Counts a redirect to the target of a link.
+	pub fn redirect(&self) {
+		self.redirects.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Counts links that were deleted because they expired.
+	pub fn links_cleaned(&self, count: u64) {
+		self.links_cleaned.fetch_add(count, Ordering::Relaxed);
+	}
+
+	/// Counts an attempt to insert a link with a random ID.
+	pub fn random_id(&self, outcome: InsertOutcome) {
+		self.id_attempts.fetch_add(1, Ordering::Relaxed);
+
+		match outcome {
+			InsertOutcome::Inserted => {},
+			InsertOutcome::ReplacedExpired => { self.id_reused_expired.fetch_add(1, Ordering::Relaxed); },
+			InsertOutcome::Occupied => { self.id_collisions.fetch_add(1, Ordering::Relaxed); },
+		}
+	}
+
+	/// Counts an error that was returned to a client.
+	pub fn error(&self, error: &ShortyError) {
+		*self.errors.lock()
+			.expect("Metrics lock was poisoned.")
+			.entry(error.variant())
+			.or_default() += 1;
+	}
+
+	/// Counts a finished request along with how long it took.
+	pub fn request(&self, status: StatusCode, duration: Duration) {
+		self.requests.fetch_add(1, Ordering::Relaxed);
+		self.latency_micros.fetch_add(u64::try_from(duration.as_micros()).unwrap_or(u64::MAX), Ordering::Relaxed);
+
+		let seconds = duration.as_secs_f64();
+		if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+			self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+		}
+
+		if status == StatusCode::NOT_FOUND {
+			self.not_found.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	/// All metrics in the Prometheus text format, including the gauges that are passed in.
+	#[must_use]
+	pub fn render(&self, total_links: i64, pool: PoolUsage) -> String {
+		let mut out = String::new();
+
+		let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+			let _ = writeln!(out, "# HELP {name} {help}");
+			let _ = writeln!(out, "# TYPE {name} {kind}");
+
+			for (labels, value) in samples {
+				let _ = writeln!(out, "{name}{labels} {value}");
+			}
+		};
+
+		let counter = |value: &AtomicU64| vec![(String::new(), value.load(Ordering::Relaxed).to_string())];
+
+		metric("shorty_links_created_total", "counter", "Links that were created.", &counter(&self.links_created));
+		metric("shorty_redirects_total", "counter", "Redirects to the targets of links.", &counter(&self.redirects));
+		metric("shorty_not_found_total", "counter", "Requests that were answered with 404 Not Found.", &counter(&self.not_found));
+		metric("shorty_links_cleaned_total", "counter", "Expired links that were deleted.", &counter(&self.links_cleaned));
+
+		let errors: Vec<(String, String)> = self.errors.lock()
+			.expect("Metrics lock was poisoned.")
+			.iter()
+			.map(|(variant, count)| (format!("{{variant=\"{variant}\"}}"), count.to_string()))
+			.collect();
+		metric("shorty_errors_total", "counter", "Errors returned to clients, by kind.", &errors);
+
+		metric("shorty_links", "gauge", "Links in the database, including expired ones that weren't cleaned up yet.", &[(String::new(), total_links.to_string())]);
+		metric("shorty_db_connections", "gauge", "Open database connections, by state.", &[
+			("{state=\"idle\"}".to_owned(), pool.idle.to_string()),
+			("{state=\"in_use\"}".to_owned(), (pool.size.saturating_sub(pool.idle)).to_string()),
+		]);
+
+		// Prometheus histogram buckets are cumulative, each one includes the ones below it.
+		let mut cumulative = 0;
+		let mut latency: Vec<(String, String)> = LATENCY_BUCKETS.iter()
+			.zip(&self.latency_buckets)
+			.map(|(bound, count)| {
+				cumulative += count.load(Ordering::Relaxed);
+				(format!("_bucket{{le=\"{bound}\"}}"), cumulative.to_string())
+			})
+			.collect();
+
+		let requests = self.requests.load(Ordering::Relaxed);
+		#[allow(clippy::cast_precision_loss)]
+		let latency_seconds = self.latency_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+
+		latency.push(("_bucket{le=\"+Inf\"}".to_owned(), requests.to_string()));
+		latency.push(("_sum".to_owned(), latency_seconds.to_string()));
+		latency.push(("_count".to_owned(), requests.to_string()));
+		metric("shorty_request_duration_seconds", "histogram", "How long requests took to be answered.", &latency);
+
+		metric("shorty_random_id_attempts_total", "counter", "Random IDs that were tried.", &counter(&self.id_attempts));
+		metric("shorty_random_id_collisions_total", "counter", "Random IDs that belonged to links that weren't expired.", &counter(&self.id_collisions));
+		metric("shorty_random_id_reused_expired_total", "counter", "Random IDs that belonged to expired links, which were replaced.", &counter(&self.id_reused_expired));
+
+
+		out
+	}
+}
+
+/// Measures every request for [`METRICS`], along with the [`ShortyError`] it was answered with.
+///
+/// # Errors
+///
+/// Passes on errors of the wrapped service.
+pub async fn track_requests(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+	let start = Instant::now();
+	let response = next.call(req).await;
+
+	let (status, error) = match &response {
+		Ok(response) => (response.status(), response.response().error()),
+		Err(why) => (why.as_response_error().status_code(), Some(why)),
+	};
+
+	if let Some(error) = error.and_then(actix_web::Error::as_error::<ShortyError>) {
+		METRICS.error(error);
+	}
+	METRICS.request(status, start.elapsed());
+
+
+	response
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::future::{Future, ready, Ready};
+use std::net::{IpAddr, Ipv6Addr};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::HttpRequest;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::CONFIG;
+use crate::error::ShortyError;
+use crate::util::client_ip;
+
+/// How often buckets that are full again get removed, so the memory use doesn't grow forever.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+	static ref CREATION_LIMITER: Option<RateLimiter> = CONFIG.load().rate_limit_creation.map(RateLimiter::new);
+	static ref RESOLUTION_LIMITER: Option<RateLimiter> = CONFIG.load().rate_limit_resolution.map(RateLimiter::new);
+}
+
+/// The budget of a single client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct RateLimitConfig {
+	/// How many requests a client can make in a row.
+	pub burst: u32,
+	/// How many requests a client regains per minute.
+	pub per_minute: u32,
+}
+
+/// The requests a client has left.
+#[derive(Debug)]
+struct TokenBucket {
+	tokens: f64,
+	last_refill: Instant,
+}
+
+/// Token bucket rate limiter with one bucket per client IP.
+#[derive(Debug)]
+pub struct RateLimiter {
+	config: RateLimitConfig,
+	buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+	last_prune: Mutex<Instant>,
+}
+
+impl RateLimiter {
+	#[must_use]
+	pub fn new(config: RateLimitConfig) -> Self {
+		Self {
+			config,
+			buckets: Mutex::new(HashMap::new()),
+			last_prune: Mutex::new(Instant::now()),
+		}
+	}
+
+	/// Takes a token from the bucket of the client.
+	///
+	/// # Errors
+	///
+	/// Returns how long the client has to wait for the next token if the bucket is empty.
+	pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+		self.check_cost(ip, 1)
+	}
+
+	/// Takes as many tokens from the bucket of the client as the request costs, or none if there aren't enough.
+	///
+	/// # Errors
+	///
+	/// Returns how long the client has to wait for enough tokens.
+	///
+	/// # Panics
+	///
+	/// Panics if the lock of the buckets was poisoned.
+	pub fn check_cost(&self, ip: IpAddr, cost: u32) -> Result<(), Duration> {
+		self.check_cost_at(ip, cost, Instant::now())
+	}
+
+	/// Like [`RateLimiter::check_cost`], with the bucket refilled up to the provided point in time.
+	fn check_cost_at(&self, ip: IpAddr, cost: u32, now: Instant) -> Result<(), Duration> {
+		let cost = f64::from(cost);
+		let capacity = f64::from(self.config.burst);
+		let per_second = f64::from(self.config.per_minute) / 60.0;
+
+		self.prune(now, capacity, per_second);
+
+		let mut buckets = self.buckets.lock().expect("Rate limiter lock was poisoned.");
+		let bucket = buckets.entry(bucket_key(ip)).or_insert(TokenBucket {
+			tokens: capacity,
+			last_refill: now,
+		});
+
+		let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+		bucket.tokens = elapsed.mul_add(per_second, bucket.tokens).min(capacity);
+		bucket.last_refill = now;
+
+		if bucket.tokens >= cost {
+			bucket.tokens -= cost;
+			return Ok(());
+		}
+
+		// Without a refill rate or with a cost the bucket can't hold, the client has to wait forever,
+		// a day is close enough.
+		let wait = if per_second > 0.0 && cost <= capacity { (cost - bucket.tokens) / per_second } else { 86_400.0 };
+
+
+		Err(Duration::from_secs_f64(wait))
+	}
+
+	/// Removes the buckets that are full again, since they are the same as new ones.
+	fn prune(&self, now: Instant, capacity: f64, per_second: f64) {
+		let mut last_prune = self.last_prune.lock().expect("Rate limiter lock was poisoned.");
+
+		if now.duration_since(*last_prune) < PRUNE_INTERVAL {
+			return;
+		}
+
+		*last_prune = now;
+
+		let mut buckets = self.buckets.lock().expect("Rate limiter lock was poisoned.");
+		let before = buckets.len();
+		buckets.retain(|_, bucket| {
+			now.duration_since(bucket.last_refill).as_secs_f64().mul_add(per_second, bucket.tokens) < capacity
+		});
+
+		debug!("Pruned {} of {before} rate limit buckets", before - buckets.len());
+	}
+}
+
+/// IPv6 clients usually get a whole /64, so they share one bucket.
+fn bucket_key(ip: IpAddr) -> IpAddr {
+	match ip {
+		IpAddr::V4(_) => ip,
+		IpAddr::V6(ip) => {
+			let prefix = u128::from(ip) & !u128::from(u64::MAX);
+
+
+			IpAddr::V6(Ipv6Addr::from(prefix))
+		},
+	}
+}
+
+/// Charges the client one token of the `rate_limit_creation` budget per link, for requests that create several.
+/// Does nothing if the budget isn't configured.
+///
+/// # Errors
+///
+/// Errors if the client doesn't have enough tokens left, no tokens are taken then.
+pub fn charge_creation(req: &HttpRequest, links: usize) -> Result<(), ShortyError> {
+	let Some((limiter, ip)) = CREATION_LIMITER.as_ref().zip(client_ip(req)) else {
+		return Ok(());
+	};
+
+	let cost = u32::try_from(links).unwrap_or(u32::MAX).max(1);
+
+
+	limiter.check_cost(ip, cost).map_err(|wait| {
+		debug!("Rate limited {ip} on a batch of {links} links");
+		ShortyError::RateLimited { retry_after: retry_after(wait) }
+	})
+}
+
+/// The wait in whole seconds, as sent in the `Retry-After` header.
+fn retry_after(wait: Duration) -> u64 {
+	wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+/// Middleware that rejects requests of clients that exceeded their budget with a `429`.
+/// Does nothing if the budget isn't configured.
+pub struct RateLimit {
+	limiter: Option<&'static RateLimiter>,
+}
+
+impl RateLimit {
+	/// Limits requests that create links, configured by `rate_limit_creation`.
+	#[must_use]
+	pub fn creation() -> Self {
+		Self { limiter: CREATION_LIMITER.as_ref() }
+	}
+
+	/// Limits requests that look up links, configured by `rate_limit_resolution`.
+	#[must_use]
+	pub fn resolution() -> Self {
+		Self { limiter: RESOLUTION_LIMITER.as_ref() }
+	}
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+	S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+	S::Future: 'static,
+	B: 'static,
+{
+	type Response = ServiceResponse<B>;
+	type Error = actix_web::Error;
+	type Transform = RateLimitMiddleware<S>;
+	type InitError = ();
+	type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+	fn new_transform(&self, service: S) -> Self::Future {
+		ready(Ok(RateLimitMiddleware { service, limiter: self.limiter }))
+	}
+}
+
+pub struct RateLimitMiddleware<S> {
+	service: S,
+	limiter: Option<&'static RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+	S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+	S::Future: 'static,
+	B: 'static,
+{
+	type Response = ServiceResponse<B>;
+	type Error = actix_web::Error;
+	type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+	forward_ready!(service);
+
+	fn call(&self, req: ServiceRequest) -> Self::Future {
+		let limited = self.limiter
+			.zip(client_ip(req.request()))
+			.and_then(|(limiter, ip)| limiter.check(ip).err().map(|wait| (ip, wait)));
+
+		if let Some((ip, wait)) = limited {
+			debug!("Rate limited {ip} on {}", req.path());
+
+
+			return Box::pin(ready(Err(ShortyError::RateLimited { retry_after: retry_after(wait) }.into())));
+		}
+
+
+		Box::pin(self.service.call(req))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::{IpAddr, Ipv4Addr};
+	use std::time::{Duration, Instant};
+
+	use super::{bucket_key, RateLimitConfig, RateLimiter, retry_after};
+
+	const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+	fn limiter(burst: u32, per_minute: u32) -> RateLimiter {
+		RateLimiter::new(RateLimitConfig { burst, per_minute })
+	}
+
+	#[test]
+	fn burst_is_capped() {
+		let limiter = limiter(3, 60);
+		let start = Instant::now();
+
+		for _ in 0..3 {
+			assert!(limiter.check_cost_at(CLIENT, 1, start).is_ok());
+		}
+		assert!(limiter.check_cost_at(CLIENT, 1, start).is_err());
+
+		// Waiting longer than needed doesn't fill the bucket beyond the burst.
+		let later = start + Duration::from_secs(100);
+		for _ in 0..3 {
+			assert!(limiter.check_cost_at(CLIENT, 1, later).is_ok());
+		}
+		assert!(limiter.check_cost_at(CLIENT, 1, later).is_err());
+	}
+
+	#[test]
+	fn tokens_refill_over_time() {
+		let limiter = limiter(2, 60);
+		let start = Instant::now();
+
+		assert!(limiter.check_cost_at(CLIENT, 2, start).is_ok());
+		assert!(limiter.check_cost_at(CLIENT, 1, start + Duration::from_millis(500)).is_err());
+
+		let refilled = start + Duration::from_secs(1);
+		assert!(limiter.check_cost_at(CLIENT, 1, refilled).is_ok());
+		assert!(limiter.check_cost_at(CLIENT, 1, refilled).is_err());
+	}
+
+	#[test]
+	fn retry_after_rounds_up() {
+		let limiter = limiter(1, 40);
+		let start = Instant::now();
+
+		assert!(limiter.check_cost_at(CLIENT, 1, start).is_ok());
+		let wait = limiter.check_cost_at(CLIENT, 1, start).unwrap_err();
+		assert_eq!(retry_after(wait), 2);
+
+		// A cost the bucket can't ever hold has to wait a day.
+		let wait = limiter.check_cost_at(CLIENT, 2, start).unwrap_err();
+		assert_eq!(retry_after(wait), 86_400);
+
+		assert_eq!(retry_after(Duration::from_secs(2)), 2);
+		assert_eq!(retry_after(Duration::from_millis(1)), 1);
+	}
+
+	#[test]
+	fn ipv6_clients_share_their_64() {
+		let ip = |address: &str| address.parse::<IpAddr>().unwrap();
+
+		assert_eq!(bucket_key(ip("2001:db8:1:2:aaaa::1")), ip("2001:db8:1:2::"));
+		assert_eq!(bucket_key(ip("2001:db8:1:2:bbbb::2")), ip("2001:db8:1:2::"));
+		assert_eq!(bucket_key(ip("2001:db8:1:3::1")), ip("2001:db8:1:3::"));
+		assert_eq!(bucket_key(CLIENT), CLIENT);
+
+		let limiter = limiter(1, 60);
+		let start = Instant::now();
+		assert!(limiter.check_cost_at(ip("2001:db8:1:2::1"), 1, start).is_ok());
+		assert!(limiter.check_cost_at(ip("2001:db8:1:2::2"), 1, start).is_err());
+		assert!(limiter.check_cost_at(ip("2001:db8:1:3::1"), 1, start).is_ok());
+	}
+}
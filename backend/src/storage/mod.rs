@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::error::ShortyError;
+use crate::link::Link;
+use crate::storage::postgres::PostgresStorage;
+use crate::storage::sqlite::SqliteStorage;
+use crate::visit::{Visit, VisitBucket, VisitCount};
+
+pub mod postgres;
+pub mod sqlite;
+
+/// The property of a visit the statistics can be broken down by.
+#[derive(Debug, Clone, Copy)]
+pub enum VisitField {
+	Referrer,
+	UserAgentFamily,
+	Country,
+}
+
+/// What happened when a link was inserted with [`StorageTransaction::insert_link_if_free`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertOutcome {
+	/// No link had the ID yet.
+	Inserted,
+	/// The link replaced an expired link with the same ID, along with its visits.
+	ReplacedExpired,
+	/// A link that isn't expired has the ID already, nothing was inserted.
+	Occupied,
+}
+
+/// How the connections to the database are used at the moment.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolUsage {
+	/// The connections that are open, idle or not.
+	pub size: u32,
+	/// The open connections that aren't used by anything.
+	pub idle: u32,
+}
+
+/// A database the links and their visits are persisted in.
+/// All patterns are SQL `LIKE` patterns that use `\` as their escape character.
+#[async_trait]
+pub trait Storage: Send + Sync {
+	/// Brings the database schema up to date.
+	async fn migrate(&self) -> Result<(), ShortyError>;
+
+	/// The versions of the migrations that weren't applied to the database yet.
+	/// Errors if the database can't be reached, so it also tells whether the database is usable.
+	async fn pending_migrations(&self) -> Result<Vec<i64>, ShortyError>;
+
+	/// Closes all connections to the database.
+	async fn close(&self);
+
+	/// How the connections to the database are used at the moment.
+	fn pool_usage(&self) -> PoolUsage;
+
+	/// Inserts the link, replacing any link that already has its ID along with its visits.
+	async fn insert_link(&self, link: &Link) -> Result<(), ShortyError>;
+
+	/// Inserts the link if no link has its ID yet, expired or not.
+	/// Returns whether the link was inserted.
+	async fn insert_link_if_absent(&self, link: &Link) -> Result<bool, ShortyError>;
+
+	/// Starts a transaction, for changes that have to happen all at once.
+	async fn begin(&self) -> Result<Box<dyn StorageTransaction>, ShortyError>;
+
+	/// Retrieves the link with the provided ID, if it exists.
+	async fn get_link(&self, id: &str) -> Result<Option<Link>, ShortyError>;
+
+	/// Counts a use of the link with the provided ID and retrieves it, as a single atomic statement.
+	/// Returns `None` without counting anything if the link doesn't exist, has no uses left or
+	/// is expired at the provided point in time. Password protected links are only used if `unlocked` is set.
+	/// The returned link includes the counted use.
+	async fn use_link(&self, id: &str, now: i64, unlocked: bool) -> Result<Option<Link>, ShortyError>;
+
+	/// Retrieves the newest link to the target that is shared by everyone who shortened it.
+	/// Shared links were created as such, aren't password protected, can be used infinitely and
+	/// have the provided lifetime and redirect type. Links that have less than half of their lifetime
+	/// left at the provided point in time are ignored, so nobody gets a link that is about to expire.
+	async fn find_shared_link(
+		&self,
+		redirect_to: &str,
+		valid_for: i64,
+		redirect_type: i64,
+		now: i64,
+	) -> Result<Option<Link>, ShortyError>;
+
+	/// Writes the target, max uses and lifetime of the link to the database.
+	async fn update_link(&self, link: &Link) -> Result<(), ShortyError>;
+
+	/// Deletes the link with the provided ID along with its visits.
+	async fn delete_link(&self, id: &str) -> Result<(), ShortyError>;
+
+	/// Retrieves a page of links whose ID or target match the pattern, newest first.
+	async fn search_links(&self, pattern: &str, limit: i64, offset: i64) -> Result<Vec<Link>, ShortyError>;
+
+	/// Retrieves a page of all links whose ID comes after the provided one, ordered by their ID.
+	async fn links_after(&self, after: &str, limit: i64) -> Result<Vec<Link>, ShortyError>;
+
+	/// Counts the links whose ID or target match the pattern.
+	async fn count_links(&self, pattern: &str) -> Result<i64, ShortyError>;
+
+	/// Retrieves all links whose target matches the pattern.
+	async fn links_to(&self, pattern: &str) -> Result<Vec<Link>, ShortyError>;
+
+	/// Deletes the links that are stale at the provided point in time.
+	/// Returns how many links were deleted.
+	async fn delete_stale_links(&self, now: i64) -> Result<u64, ShortyError>;
+
+	/// Inserts a visit of a link.
+	async fn insert_visit(&self, visit: &Visit) -> Result<(), ShortyError>;
+
+	/// Counts the visits of a link in time buckets of the provided length, oldest first.
+	async fn visit_buckets(&self, link_id: &str, interval_millis: i64) -> Result<Vec<VisitBucket>, ShortyError>;
+
+	/// Counts the visits of a link grouped by the field, most common first.
+	async fn visit_breakdown(
+		&self,
+		link_id: &str,
+		field: VisitField,
+		limit: i64,
+	) -> Result<Vec<VisitCount>, ShortyError>;
+}
+
+/// A transaction of a [`Storage`].
+/// Everything done through it is discarded if it is dropped without being committed.
+#[async_trait]
+pub trait StorageTransaction: Send {
+	/// Inserts the link if its ID is free.
+	/// An ID is free if no link has it or the link that has it is expired at the provided point in time,
+	/// in which case that link is replaced along with its visits.
+	async fn insert_link_if_free(&mut self, link: &Link, now: i64) -> Result<InsertOutcome, ShortyError>;
+
+	/// Makes the changes of the transaction permanent.
+	async fn commit(self: Box<Self>) -> Result<(), ShortyError>;
+}
+
+/// Connects to the database at the location.
+/// URLs with the `postgres` or `postgresql` scheme are connected to as PostgreSQL databases,
+/// everything else is treated as the path of a SQLite database.
+///
+/// # Errors
+///
+/// Errors if the database can't be connected to.
+pub async fn connect(database_location: &str) -> Result<Arc<dyn Storage>, ShortyError> {
+	if database_location.starts_with("postgres://") || database_location.starts_with("postgresql://") {
+		let storage = PostgresStorage::connect(database_location).await?;
+
+
+		Ok(Arc::new(storage))
+	} else {
+		let storage = SqliteStorage::connect(database_location).await?;
+
+
+		Ok(Arc::new(storage))
+	}
+}
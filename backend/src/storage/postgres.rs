@@ -0,0 +1,456 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres, Transaction};
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgPoolOptions;
+
+use crate::error::ShortyError;
+use crate::link::Link;
+use crate::storage::{InsertOutcome, PoolUsage, Storage, StorageTransaction, VisitField};
+use crate::visit::{Visit, VisitBucket, VisitCount};
+
+/// The migrations that bring the PostgreSQL schema up to date.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Stores everything in a PostgreSQL database, which can be shared by several instances.
+// The query macros only check against one database, so the queries here are checked at runtime.
+pub struct PostgresStorage {
+	pool: Pool<Postgres>,
+}
+
+impl PostgresStorage {
+	/// Connects to the PostgreSQL database at the URL.
+	///
+	/// # Errors
+	///
+	/// Errors if the database can't be connected to.
+	pub async fn connect(url: &str) -> Result<Self, ShortyError> {
+		let pool = PgPoolOptions::new()
+			.max_connections(5)
+			.min_connections(1)
+			.max_lifetime(Some(Duration::from_secs(60 * 60)))
+			.connect(url)
+			.await?;
+
+
+		Ok(Self { pool })
+	}
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+	async fn migrate(&self) -> Result<(), ShortyError> {
+		MIGRATOR
+			.run(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn close(&self) {
+		self.pool.close().await;
+	}
+
+	async fn pending_migrations(&self) -> Result<Vec<i64>, ShortyError> {
+		let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+			.fetch_all(&self.pool)
+			.await?;
+
+		let pending = MIGRATOR.iter()
+			.map(|migration| migration.version)
+			.filter(|version| !applied.contains(version))
+			.collect();
+
+
+		Ok(pending)
+	}
+
+	fn pool_usage(&self) -> PoolUsage {
+		PoolUsage {
+			size: self.pool.size(),
+			// The idle connections are counted separately, so they might not add up exactly.
+			idle: u32::try_from(self.pool.num_idle()).unwrap_or(u32::MAX).min(self.pool.size()),
+		}
+	}
+
+	async fn insert_link(&self, link: &Link) -> Result<(), ShortyError> {
+		// Deleting the old link first, instead of an upsert, also deletes its visits.
+		let mut transaction = self.pool.begin().await?;
+
+		sqlx::query("DELETE FROM links WHERE id = $1")
+			.bind(link.id.as_str())
+			.execute(&mut transaction)
+			.await?;
+
+		sqlx::query(
+			r#"
+			INSERT INTO links (id, redirect_to, max_uses, invocations, created_at, valid_for, management_token_hash, password_hash, redirect_type, shared)
+			VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+			"#
+		)
+			.bind(link.id.as_str())
+			.bind(link.redirect_to.as_str())
+			.bind(link.max_uses)
+			.bind(link.invocations)
+			.bind(link.created_at)
+			.bind(link.valid_for)
+			.bind(link.management_token_hash.as_deref())
+			.bind(link.password_hash.as_deref())
+			.bind(link.redirect_type)
+			.bind(link.shared)
+			.execute(&mut transaction)
+			.await?;
+
+		transaction.commit().await?;
+
+
+		Ok(())
+	}
+
+	async fn insert_link_if_absent(&self, link: &Link) -> Result<bool, ShortyError> {
+		let res = sqlx::query(
+			r#"
+			INSERT INTO links (id, redirect_to, max_uses, invocations, created_at, valid_for, management_token_hash, password_hash, redirect_type, shared)
+			VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+			ON CONFLICT (id) DO NOTHING
+			"#
+		)
+			.bind(link.id.as_str())
+			.bind(link.redirect_to.as_str())
+			.bind(link.max_uses)
+			.bind(link.invocations)
+			.bind(link.created_at)
+			.bind(link.valid_for)
+			.bind(link.management_token_hash.as_deref())
+			.bind(link.password_hash.as_deref())
+			.bind(link.redirect_type)
+			.bind(link.shared)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(res.rows_affected() > 0)
+	}
+
+	async fn begin(&self) -> Result<Box<dyn StorageTransaction>, ShortyError> {
+		let transaction = self.pool.begin().await?;
+
+
+		Ok(Box::new(PostgresTransaction { transaction }))
+	}
+
+	async fn get_link(&self, id: &str) -> Result<Option<Link>, ShortyError> {
+		let link = sqlx::query_as("SELECT * FROM links WHERE id = $1")
+			.bind(id)
+			.fetch_optional(&self.pool)
+			.await?;
+
+
+		Ok(link)
+	}
+
+	async fn use_link(&self, id: &str, now: i64, unlocked: bool) -> Result<Option<Link>, ShortyError> {
+		let link = sqlx::query_as(
+			r#"
+			UPDATE links
+			SET invocations = invocations + 1
+			WHERE id = $1
+			AND (max_uses = 0 OR invocations < max_uses)
+			AND (valid_for = 0 OR $2 - created_at <= valid_for)
+			AND max_uses >= 0 AND valid_for >= 0
+			AND ($3 OR password_hash IS NULL)
+			RETURNING *
+			"#
+		)
+			.bind(id)
+			.bind(now)
+			.bind(unlocked)
+			.fetch_optional(&self.pool)
+			.await?;
+
+
+		Ok(link)
+	}
+
+	async fn find_shared_link(
+		&self,
+		redirect_to: &str,
+		valid_for: i64,
+		redirect_type: i64,
+		now: i64,
+	) -> Result<Option<Link>, ShortyError> {
+		let link = sqlx::query_as(
+			r#"
+			SELECT * FROM links
+			WHERE redirect_to = $1
+			AND shared AND password_hash IS NULL
+			AND max_uses = 0 AND valid_for = $2 AND redirect_type = $3
+			AND (valid_for = 0 OR 2 * (created_at + valid_for - $4) >= valid_for)
+			ORDER BY created_at DESC
+			LIMIT 1
+			"#
+		)
+			.bind(redirect_to)
+			.bind(valid_for)
+			.bind(redirect_type)
+			.bind(now)
+			.fetch_optional(&self.pool)
+			.await?;
+
+
+		Ok(link)
+	}
+
+	async fn update_link(&self, link: &Link) -> Result<(), ShortyError> {
+		sqlx::query(
+			r#"
+			UPDATE links
+			SET redirect_to = $1, max_uses = $2, valid_for = $3, redirect_type = $4
+			WHERE id = $5
+			"#
+		)
+			.bind(link.redirect_to.as_str())
+			.bind(link.max_uses)
+			.bind(link.valid_for)
+			.bind(link.redirect_type)
+			.bind(link.shared)
+			.bind(link.id.as_str())
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn delete_link(&self, id: &str) -> Result<(), ShortyError> {
+		sqlx::query("DELETE FROM links WHERE id = $1")
+			.bind(id)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn search_links(&self, pattern: &str, limit: i64, offset: i64) -> Result<Vec<Link>, ShortyError> {
+		let links = sqlx::query_as(
+			r#"
+			SELECT * FROM links
+			WHERE id LIKE $1 ESCAPE '\'
+			OR redirect_to LIKE $1 ESCAPE '\'
+			ORDER BY created_at DESC
+			LIMIT $2 OFFSET $3
+			"#
+		)
+			.bind(pattern)
+			.bind(limit)
+			.bind(offset)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(links)
+	}
+
+	async fn links_after(&self, after: &str, limit: i64) -> Result<Vec<Link>, ShortyError> {
+		let links = sqlx::query_as(
+			r#"
+			SELECT * FROM links
+			WHERE id > $1
+			ORDER BY id
+			LIMIT $2
+			"#
+		)
+			.bind(after)
+			.bind(limit)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(links)
+	}
+
+	async fn count_links(&self, pattern: &str) -> Result<i64, ShortyError> {
+		let (count,) = sqlx::query_as(
+			r#"
+			SELECT COUNT(*) FROM links
+			WHERE id LIKE $1 ESCAPE '\'
+			OR redirect_to LIKE $1 ESCAPE '\'
+			"#
+		)
+			.bind(pattern)
+			.fetch_one(&self.pool)
+			.await?;
+
+
+		Ok(count)
+	}
+
+	async fn links_to(&self, pattern: &str) -> Result<Vec<Link>, ShortyError> {
+		let links = sqlx::query_as(
+			r#"
+			SELECT * FROM links
+			WHERE redirect_to LIKE $1 ESCAPE '\'
+			"#
+		)
+			.bind(pattern)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(links)
+	}
+
+	async fn delete_stale_links(&self, now: i64) -> Result<u64, ShortyError> {
+		let res = sqlx::query(
+			r#"
+			DELETE FROM links
+			WHERE (max_uses != 0 AND invocations >= max_uses)
+			OR (valid_for != 0 AND created_at + valid_for < $1)
+			"#
+		)
+			.bind(now)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(res.rows_affected())
+	}
+
+	async fn insert_visit(&self, visit: &Visit) -> Result<(), ShortyError> {
+		sqlx::query(
+			r#"
+			INSERT INTO visits (link_id, visited_at, referrer, user_agent_family, country)
+			VALUES ($1, $2, $3, $4, $5)
+			"#
+		)
+			.bind(visit.link_id.as_str())
+			.bind(visit.visited_at)
+			.bind(visit.referrer.as_deref())
+			.bind(visit.user_agent_family.as_deref())
+			.bind(visit.country.as_deref())
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn visit_buckets(&self, link_id: &str, interval_millis: i64) -> Result<Vec<VisitBucket>, ShortyError> {
+		let buckets = sqlx::query_as(
+			r#"
+			SELECT (visited_at / $1) * $1 AS start, COUNT(*) AS visits
+			FROM visits
+			WHERE link_id = $2
+			GROUP BY 1
+			ORDER BY 1
+			"#
+		)
+			.bind(interval_millis)
+			.bind(link_id)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(buckets)
+	}
+
+	async fn visit_breakdown(
+		&self,
+		link_id: &str,
+		field: VisitField,
+		limit: i64,
+	) -> Result<Vec<VisitCount>, ShortyError> {
+		let column = match field {
+			VisitField::Referrer => "referrer",
+			VisitField::UserAgentFamily => "user_agent_family",
+			VisitField::Country => "country",
+		};
+
+		let counts = sqlx::query_as(
+			format!(
+				r#"
+				SELECT {column} AS value, COUNT(*) AS visits
+				FROM visits
+				WHERE link_id = $1
+				GROUP BY 1
+				ORDER BY 2 DESC
+				LIMIT $2
+				"#
+			).as_str()
+		)
+			.bind(link_id)
+			.bind(limit)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(counts)
+	}
+}
+
+/// A transaction of a [`PostgresStorage`].
+pub struct PostgresTransaction {
+	transaction: Transaction<'static, Postgres>,
+}
+
+#[async_trait]
+impl StorageTransaction for PostgresTransaction {
+	async fn insert_link_if_free(&mut self, link: &Link, now: i64) -> Result<InsertOutcome, ShortyError> {
+		// A concurrent transaction that deletes the same link makes this one wait for it,
+		// afterwards the insert of one of them runs into the conflict.
+		let deleted = sqlx::query(
+			r#"
+			DELETE FROM links
+			WHERE id = $1
+			AND NOT (
+				(max_uses = 0 OR invocations < max_uses)
+				AND (valid_for = 0 OR $2 - created_at <= valid_for)
+				AND max_uses >= 0 AND valid_for >= 0
+			)
+			"#
+		)
+			.bind(link.id.as_str())
+			.bind(now)
+			.execute(&mut self.transaction)
+			.await?;
+
+		let inserted = sqlx::query(
+			r#"
+			INSERT INTO links (id, redirect_to, max_uses, invocations, created_at, valid_for, management_token_hash, password_hash, redirect_type, shared)
+			VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+			ON CONFLICT (id) DO NOTHING
+			"#
+		)
+			.bind(link.id.as_str())
+			.bind(link.redirect_to.as_str())
+			.bind(link.max_uses)
+			.bind(link.invocations)
+			.bind(link.created_at)
+			.bind(link.valid_for)
+			.bind(link.management_token_hash.as_deref())
+			.bind(link.password_hash.as_deref())
+			.bind(link.redirect_type)
+			.bind(link.shared)
+			.execute(&mut self.transaction)
+			.await?;
+
+		let outcome = match (inserted.rows_affected(), deleted.rows_affected()) {
+			(0, _) => InsertOutcome::Occupied,
+			(_, 0) => InsertOutcome::Inserted,
+			_ => InsertOutcome::ReplacedExpired,
+		};
+
+
+		Ok(outcome)
+	}
+
+	async fn commit(self: Box<Self>) -> Result<(), ShortyError> {
+		self.transaction.commit().await?;
+
+
+		Ok(())
+	}
+}
@@ -0,0 +1,532 @@
+#[cfg(test)]
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::{Pool, Sqlite, Transaction};
+use sqlx::migrate::{MigrateDatabase, Migrator};
+use sqlx::sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+
+use crate::error::ShortyError;
+use crate::link::Link;
+use crate::storage::{InsertOutcome, PoolUsage, Storage, StorageTransaction, VisitField};
+use crate::visit::{Visit, VisitBucket, VisitCount};
+
+/// The migrations that bring the SQLite schema up to date.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// Stores everything in a single SQLite database file.
+pub struct SqliteStorage {
+	pool: Pool<Sqlite>,
+}
+
+impl SqliteStorage {
+	/// Opens the SQLite database at the path, creating it if it doesn't exist yet.
+	///
+	/// # Errors
+	///
+	/// Errors if the database can't be created or opened.
+	pub async fn connect(path: &str) -> Result<Self, ShortyError> {
+		if !Sqlite::database_exists(path).await? {
+			Sqlite::create_database(path).await?;
+		}
+
+		let db_options = SqliteConnectOptions::new()
+			.auto_vacuum(SqliteAutoVacuum::Full)
+			.journal_mode(SqliteJournalMode::Wal)
+			.filename(path);
+
+
+		Self::open(db_options).await
+	}
+
+	/// Opens a database that only lives in memory, shared by all connections of the pool.
+	/// It is gone once the last connection is closed.
+	///
+	/// # Errors
+	///
+	/// Errors if the database can't be opened.
+	#[cfg(test)]
+	pub async fn in_memory() -> Result<Self, ShortyError> {
+		let db_options = SqliteConnectOptions::from_str("sqlite::memory:")?;
+
+
+		Self::open(db_options).await
+	}
+
+	async fn open(db_options: SqliteConnectOptions) -> Result<Self, ShortyError> {
+		let pool = SqlitePoolOptions::new()
+			.max_connections(5)
+			.min_connections(1)
+			.max_lifetime(Some(Duration::from_secs(60 * 60)))
+			.connect_with(db_options)
+			.await?;
+
+
+		Ok(Self { pool })
+	}
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+	async fn migrate(&self) -> Result<(), ShortyError> {
+		MIGRATOR
+			.run(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn close(&self) {
+		self.pool.close().await;
+	}
+
+	async fn pending_migrations(&self) -> Result<Vec<i64>, ShortyError> {
+		let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+			.fetch_all(&self.pool)
+			.await?;
+
+		let pending = MIGRATOR.iter()
+			.map(|migration| migration.version)
+			.filter(|version| !applied.contains(version))
+			.collect();
+
+
+		Ok(pending)
+	}
+
+	fn pool_usage(&self) -> PoolUsage {
+		PoolUsage {
+			size: self.pool.size(),
+			// The idle connections are counted separately, so they might not add up exactly.
+			idle: u32::try_from(self.pool.num_idle()).unwrap_or(u32::MAX).min(self.pool.size()),
+		}
+	}
+
+	async fn insert_link(&self, link: &Link) -> Result<(), ShortyError> {
+		sqlx::query!(
+			r#"
+				INSERT OR REPLACE INTO links
+				VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+			"#,
+			link.id,
+			link.redirect_to,
+			link.max_uses,
+			link.invocations,
+			link.created_at,
+			link.valid_for,
+			link.management_token_hash,
+			link.password_hash,
+			link.redirect_type,
+			link.shared
+		)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn insert_link_if_absent(&self, link: &Link) -> Result<bool, ShortyError> {
+		let res = sqlx::query!(
+			r#"
+				INSERT OR IGNORE INTO links
+				VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+			"#,
+			link.id,
+			link.redirect_to,
+			link.max_uses,
+			link.invocations,
+			link.created_at,
+			link.valid_for,
+			link.management_token_hash,
+			link.password_hash,
+			link.redirect_type,
+			link.shared
+		)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(res.rows_affected() > 0)
+	}
+
+	async fn begin(&self) -> Result<Box<dyn StorageTransaction>, ShortyError> {
+		let transaction = self.pool.begin().await?;
+
+
+		Ok(Box::new(SqliteTransaction { transaction }))
+	}
+
+	async fn get_link(&self, id: &str) -> Result<Option<Link>, ShortyError> {
+		let link = sqlx::query_as!(
+			Link,
+			r#"
+			SELECT * FROM links
+			WHERE id = $1;
+			"#,
+			id,
+		)
+			.fetch_optional(&self.pool)
+			.await?;
+
+
+		Ok(link)
+	}
+
+	async fn use_link(&self, id: &str, now: i64, unlocked: bool) -> Result<Option<Link>, ShortyError> {
+		let link = sqlx::query_as!(
+			Link,
+			r#"
+			UPDATE links
+			SET invocations = invocations + 1
+			WHERE id = $1
+			AND (max_uses = 0 OR invocations < max_uses)
+			AND (valid_for = 0 OR $2 - created_at <= valid_for)
+			AND max_uses >= 0 AND valid_for >= 0
+			AND ($3 OR password_hash IS NULL)
+			RETURNING
+				id AS "id!", redirect_to AS "redirect_to!", max_uses AS "max_uses!",
+				invocations AS "invocations!", created_at AS "created_at!", valid_for AS "valid_for!",
+				management_token_hash, password_hash, redirect_type AS "redirect_type!",
+				shared AS "shared!: bool"
+			"#,
+			id,
+			now,
+			unlocked
+		)
+			.fetch_optional(&self.pool)
+			.await?;
+
+
+		Ok(link)
+	}
+
+	async fn find_shared_link(
+		&self,
+		redirect_to: &str,
+		valid_for: i64,
+		redirect_type: i64,
+		now: i64,
+	) -> Result<Option<Link>, ShortyError> {
+		let link = sqlx::query_as!(
+			Link,
+			r#"
+			SELECT
+				id AS "id!", redirect_to AS "redirect_to!", max_uses AS "max_uses!",
+				invocations AS "invocations!", created_at AS "created_at!", valid_for AS "valid_for!",
+				management_token_hash, password_hash, redirect_type AS "redirect_type!",
+				shared AS "shared!: bool"
+			FROM links
+			WHERE redirect_to = $1
+			AND shared AND password_hash IS NULL
+			AND max_uses = 0 AND valid_for = $2 AND redirect_type = $3
+			AND (valid_for = 0 OR 2 * (created_at + valid_for - $4) >= valid_for)
+			ORDER BY created_at DESC
+			LIMIT 1
+			"#,
+			redirect_to,
+			valid_for,
+			redirect_type,
+			now
+		)
+			.fetch_optional(&self.pool)
+			.await?;
+
+
+		Ok(link)
+	}
+
+	async fn update_link(&self, link: &Link) -> Result<(), ShortyError> {
+		sqlx::query!(
+			r#"
+			UPDATE links
+			SET redirect_to = $1, max_uses = $2, valid_for = $3, redirect_type = $4
+			WHERE id = $5
+			"#,
+			link.redirect_to,
+			link.max_uses,
+			link.valid_for,
+			link.redirect_type,
+			link.id
+		)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn delete_link(&self, id: &str) -> Result<(), ShortyError> {
+		sqlx::query!(
+			r#"
+			DELETE FROM links
+			WHERE id = $1
+			"#,
+			id
+		)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn search_links(&self, pattern: &str, limit: i64, offset: i64) -> Result<Vec<Link>, ShortyError> {
+		let links = sqlx::query_as!(
+			Link,
+			r#"
+			SELECT
+				id AS "id!", redirect_to AS "redirect_to!", max_uses AS "max_uses!",
+				invocations AS "invocations!", created_at AS "created_at!", valid_for AS "valid_for!",
+				management_token_hash, password_hash, redirect_type AS "redirect_type!",
+				shared AS "shared!: bool"
+			FROM links
+			WHERE id LIKE $1 ESCAPE '\'
+			OR redirect_to LIKE $1 ESCAPE '\'
+			ORDER BY created_at DESC
+			LIMIT $2 OFFSET $3
+			"#,
+			pattern,
+			limit,
+			offset
+		)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(links)
+	}
+
+	async fn links_after(&self, after: &str, limit: i64) -> Result<Vec<Link>, ShortyError> {
+		let links = sqlx::query_as!(
+			Link,
+			r#"
+			SELECT
+				id AS "id!", redirect_to AS "redirect_to!", max_uses AS "max_uses!",
+				invocations AS "invocations!", created_at AS "created_at!", valid_for AS "valid_for!",
+				management_token_hash, password_hash, redirect_type AS "redirect_type!",
+				shared AS "shared!: bool"
+			FROM links
+			WHERE id > $1
+			ORDER BY id
+			LIMIT $2
+			"#,
+			after,
+			limit
+		)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(links)
+	}
+
+	async fn count_links(&self, pattern: &str) -> Result<i64, ShortyError> {
+		let res = sqlx::query!(
+			r#"
+			SELECT COUNT(*) AS "count!: i64" FROM links
+			WHERE id LIKE $1 ESCAPE '\'
+			OR redirect_to LIKE $1 ESCAPE '\'
+			"#,
+			pattern
+		)
+			.fetch_one(&self.pool)
+			.await?;
+
+
+		Ok(res.count)
+	}
+
+	async fn links_to(&self, pattern: &str) -> Result<Vec<Link>, ShortyError> {
+		let links = sqlx::query_as!(
+			Link,
+			r#"
+			SELECT * FROM links
+			WHERE redirect_to LIKE $1 ESCAPE '\'
+			"#,
+			pattern
+		)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(links)
+	}
+
+	async fn delete_stale_links(&self, now: i64) -> Result<u64, ShortyError> {
+		let res = sqlx::query!(
+			r#"
+			DELETE FROM links
+			WHERE (max_uses != 0 AND invocations >= max_uses)
+			OR (valid_for != 0 AND created_at + valid_for < $1)
+			"#,
+			now
+		)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(res.rows_affected())
+	}
+
+	async fn insert_visit(&self, visit: &Visit) -> Result<(), ShortyError> {
+		sqlx::query!(
+			r#"
+			INSERT INTO visits
+			VALUES ($1, $2, $3, $4, $5)
+			"#,
+			visit.link_id,
+			visit.visited_at,
+			visit.referrer,
+			visit.user_agent_family,
+			visit.country
+		)
+			.execute(&self.pool)
+			.await?;
+
+
+		Ok(())
+	}
+
+	async fn visit_buckets(&self, link_id: &str, interval_millis: i64) -> Result<Vec<VisitBucket>, ShortyError> {
+		let buckets = sqlx::query_as!(
+			VisitBucket,
+			r#"
+			SELECT (visited_at / $1) * $1 AS "start!: i64", COUNT(*) AS "visits!: i64"
+			FROM visits
+			WHERE link_id = $2
+			GROUP BY 1
+			ORDER BY 1
+			"#,
+			interval_millis,
+			link_id
+		)
+			.fetch_all(&self.pool)
+			.await?;
+
+
+		Ok(buckets)
+	}
+
+	async fn visit_breakdown(
+		&self,
+		link_id: &str,
+		field: VisitField,
+		limit: i64,
+	) -> Result<Vec<VisitCount>, ShortyError> {
+		// The query macros need the column names at compile time, hence one query per field.
+		let counts = match field {
+			VisitField::Referrer => sqlx::query_as!(
+				VisitCount,
+				r#"
+				SELECT referrer AS value, COUNT(*) AS "visits!: i64"
+				FROM visits
+				WHERE link_id = $1
+				GROUP BY 1
+				ORDER BY 2 DESC
+				LIMIT $2
+				"#,
+				link_id,
+				limit
+			)
+				.fetch_all(&self.pool)
+				.await?,
+			VisitField::UserAgentFamily => sqlx::query_as!(
+				VisitCount,
+				r#"
+				SELECT user_agent_family AS value, COUNT(*) AS "visits!: i64"
+				FROM visits
+				WHERE link_id = $1
+				GROUP BY 1
+				ORDER BY 2 DESC
+				LIMIT $2
+				"#,
+				link_id,
+				limit
+			)
+				.fetch_all(&self.pool)
+				.await?,
+			VisitField::Country => sqlx::query_as!(
+				VisitCount,
+				r#"
+				SELECT country AS value, COUNT(*) AS "visits!: i64"
+				FROM visits
+				WHERE link_id = $1
+				GROUP BY 1
+				ORDER BY 2 DESC
+				LIMIT $2
+				"#,
+				link_id,
+				limit
+			)
+				.fetch_all(&self.pool)
+				.await?,
+		};
+
+
+		Ok(counts)
+	}
+}
+
+/// A transaction of a [`SqliteStorage`].
+pub struct SqliteTransaction {
+	transaction: Transaction<'static, Sqlite>,
+}
+
+#[async_trait]
+impl StorageTransaction for SqliteTransaction {
+	async fn insert_link_if_free(&mut self, link: &Link, now: i64) -> Result<InsertOutcome, ShortyError> {
+		let deleted = sqlx::query!(
+			r#"
+			DELETE FROM links
+			WHERE id = $1
+			AND NOT (
+				(max_uses = 0 OR invocations < max_uses)
+				AND (valid_for = 0 OR $2 - created_at <= valid_for)
+				AND max_uses >= 0 AND valid_for >= 0
+			)
+			"#,
+			link.id,
+			now,
+		)
+			.execute(&mut self.transaction)
+			.await?;
+
+		let inserted = sqlx::query!(
+			r#"
+				INSERT OR IGNORE INTO links
+				VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+			"#,
+			link.id,
+			link.redirect_to,
+			link.max_uses,
+			link.invocations,
+			link.created_at,
+			link.valid_for,
+			link.management_token_hash,
+			link.password_hash,
+			link.redirect_type,
+			link.shared
+		)
+			.execute(&mut self.transaction)
+			.await?;
+
+		let outcome = match (inserted.rows_affected(), deleted.rows_affected()) {
+			(0, _) => InsertOutcome::Occupied,
+			(_, 0) => InsertOutcome::Inserted,
+			_ => InsertOutcome::ReplacedExpired,
+		};
+
+
+		Ok(outcome)
+	}
+
+	async fn commit(self: Box<Self>) -> Result<(), ShortyError> {
+		self.transaction.commit().await?;
+
+
+		Ok(())
+	}
+}
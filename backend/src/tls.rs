@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::header::LOCATION;
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls_pemfile::Item;
+use tracing::{error, info};
+
+use crate::CONFIG;
+use crate::error::ShortyError;
+
+/// Hands out the configured certificate, which can be swapped out while the server is running.
+pub struct CertificateResolver {
+	cert_path: String,
+	key_path: String,
+	key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateResolver {
+	/// Loads the certificate configured by `tls_cert` and `tls_key`.
+	/// Returns `None` if neither is set, HTTPS is disabled then.
+	///
+	/// # Errors
+	///
+	/// Errors if only one of them is set or the certificate can't be loaded.
+	pub fn from_config() -> Result<Option<Arc<Self>>, ShortyError> {
+		let config = CONFIG.load();
+
+		match (&config.tls_cert, &config.tls_key) {
+			(Some(cert_path), Some(key_path)) => {
+				let key = load_certified_key(cert_path, key_path)?;
+
+
+				Ok(Some(Arc::new(Self {
+					cert_path: cert_path.clone(),
+					key_path: key_path.clone(),
+					key: RwLock::new(Arc::new(key)),
+				})))
+			},
+			(None, None) => Ok(None),
+			_ => Err(ShortyError::Tls("`tls_cert` and `tls_key` have to be set together.".to_owned())),
+		}
+	}
+
+	/// Loads the certificate from disk again, new connections use the new one.
+	/// If loading fails the current certificate is kept.
+	///
+	/// # Errors
+	///
+	/// Errors if the certificate can't be loaded.
+	pub fn reload(&self) -> Result<(), ShortyError> {
+		let key = load_certified_key(self.cert_path.as_str(), self.key_path.as_str())?;
+		*self.key.write().expect("Certificate lock was poisoned.") = Arc::new(key);
+
+
+		Ok(())
+	}
+
+	/// The rustls config for the HTTPS listener, using this resolver for its certificate.
+	#[must_use]
+	pub fn server_config(self: Arc<Self>) -> ServerConfig {
+		ServerConfig::builder()
+			.with_safe_defaults()
+			.with_no_client_auth()
+			.with_cert_resolver(self)
+	}
+}
+
+impl ResolvesServerCert for CertificateResolver {
+	fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+		Some(self.key.read().expect("Certificate lock was poisoned.").clone())
+	}
+}
+
+/// Reloads the certificate every time the process receives a SIGHUP.
+#[cfg(unix)]
+pub fn reload_on_sighup(resolver: Arc<CertificateResolver>) {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	let mut hangup = signal(SignalKind::hangup()).expect("Error listening for SIGHUP.");
+
+	tokio::task::spawn(async move {
+		while hangup.recv().await.is_some() {
+			match resolver.reload() {
+				Ok(()) => info!("Reloaded the TLS certificate."),
+				Err(why) => error!("Keeping the old TLS certificate: {why}"),
+			}
+		}
+	});
+}
+
+/// Reads the certificate chain and the first private key from the PEM files.
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, ShortyError> {
+	let open = |path: &str| {
+		File::open(path)
+			.map(BufReader::new)
+			.map_err(|why| ShortyError::Tls(format!("Couldn't open {path}: {why}")))
+	};
+
+	let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+		.map_err(|why| ShortyError::Tls(format!("Couldn't read {cert_path}: {why}")))?;
+
+	if certs.is_empty() {
+		return Err(ShortyError::Tls(format!("{cert_path} doesn't contain a certificate.")));
+	}
+
+	let key = rustls_pemfile::read_all(&mut open(key_path)?)
+		.map_err(|why| ShortyError::Tls(format!("Couldn't read {key_path}: {why}")))?
+		.into_iter()
+		.find_map(|item| match item {
+			Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+			_ => None,
+		})
+		.ok_or_else(|| ShortyError::Tls(format!("{key_path} doesn't contain a private key.")))?;
+
+	let signing_key = rustls::sign::any_supported_type(&key)
+		.map_err(|why| ShortyError::Tls(format!("Unsupported private key in {key_path}: {why}")))?;
+
+
+	Ok(CertifiedKey::new(certs.into_iter().map(Certificate).collect(), signing_key))
+}
+
+/// Redirects the request to the same location on the HTTPS listener.
+// The function is async because actix-web requires handlers to be.
+#[allow(clippy::unused_async)]
+pub async fn redirect_to_https(req: HttpRequest) -> HttpResponse {
+	let connection_info = req.connection_info();
+	let host = strip_port(connection_info.host());
+	let port = match CONFIG.load().port {
+		443 => String::new(),
+		port => format!(":{port}"),
+	};
+	let path = req.uri()
+		.path_and_query()
+		.map_or("/", |path_and_query| path_and_query.as_str());
+
+
+	HttpResponse::PermanentRedirect()
+		.append_header((LOCATION, format!("https://{host}{port}{path}")))
+		.finish()
+}
+
+/// Removes the port from a host like `example.com:80` or `[::1]:80`.
+fn strip_port(host: &str) -> &str {
+	match host.rsplit_once(':') {
+		Some((name, port)) if !name.ends_with(':') && port.chars().all(|c| c.is_ascii_digit()) => name,
+		_ => host,
+	}
+}
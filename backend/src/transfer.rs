@@ -0,0 +1,522 @@
+use std::sync::Arc;
+
+use actix_web::web::Bytes;
+use futures_util::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::ShortyError;
+use crate::link::{Link, RedirectType};
+use crate::storage::Storage;
+
+/// How many links are read from the database at once while exporting.
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// How many suffixes are tried to find a free ID for a link that gets renamed.
+const MAX_RENAME_ATTEMPTS: u32 = 100;
+
+/// The columns of CSV exports, in the order of the fields of [`LinkRecord`].
+const CSV_HEADER: [&str; 10] = [
+	"id", "redirect_to", "max_uses", "invocations", "created_at", "valid_for",
+	"management_token_hash", "password_hash", "redirect_type", "shared",
+];
+
+/// The file formats links can be exported to and imported from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+	/// One JSON object per line.
+	#[default]
+	Jsonl,
+	/// Comma separated values with a header row.
+	Csv,
+}
+
+impl Format {
+	#[must_use]
+	pub const fn content_type(self) -> &'static str {
+		match self {
+			Format::Jsonl => "application/x-ndjson",
+			Format::Csv => "text/csv; charset=utf-8",
+		}
+	}
+
+	#[must_use]
+	pub const fn extension(self) -> &'static str {
+		match self {
+			Format::Jsonl => "jsonl",
+			Format::Csv => "csv",
+		}
+	}
+}
+
+/// What happens to imported links whose ID is already used by an existing link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+	/// Keep the existing link and drop the imported one.
+	#[default]
+	Skip,
+	/// Replace the existing link, along with its visits.
+	Overwrite,
+	/// Import the link with a suffix like `-2` appended to its ID.
+	Rename,
+}
+
+/// A link as it is exported, with everything that is needed to restore it.
+/// The management token and the password only exist as hashes, so they keep working after an import.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkRecord {
+	id: String,
+	redirect_to: String,
+	max_uses: i64,
+	invocations: i64,
+	created_at: i64,
+	valid_for: i64,
+	management_token_hash: Option<String>,
+	password_hash: Option<String>,
+	redirect_type: i64,
+	/// Missing in exports of older versions, which didn't mark shared links.
+	#[serde(default)]
+	shared: bool,
+}
+
+impl From<&Link> for LinkRecord {
+	fn from(link: &Link) -> Self {
+		Self {
+			id: link.id.clone(),
+			redirect_to: link.redirect_to.clone(),
+			max_uses: link.max_uses,
+			invocations: link.invocations,
+			created_at: link.created_at,
+			valid_for: link.valid_for,
+			management_token_hash: link.management_token_hash.clone(),
+			password_hash: link.password_hash.clone(),
+			redirect_type: link.redirect_type,
+			shared: link.shared,
+		}
+	}
+}
+
+impl TryFrom<LinkRecord> for Link {
+	type Error = String;
+
+	fn try_from(record: LinkRecord) -> Result<Self, Self::Error> {
+		if record.id.is_empty() {
+			return Err("The ID is empty.".to_owned());
+		}
+
+		// Unlike custom IDs, imported IDs aren't changed, the links are expected to keep their IDs.
+		let id = Link::validate_custom_id(record.id.as_str()).map_err(|why| why.to_string())?;
+		if id != record.id {
+			return Err("The ID contains chars that can't be used in URLs, like spaces or slashes.".to_owned());
+		}
+
+		u16::try_from(record.redirect_type)
+			.map_err(|_| format!("{} is not a supported redirect type", record.redirect_type))
+			.and_then(RedirectType::try_from)?;
+
+		// Imported links must not redirect anywhere links created through the API can't.
+		let redirect_to = Link::validate_redirect(record.redirect_to.as_str())
+			.map_err(|why| why.to_string())?;
+
+
+		Ok(Self {
+			id: record.id,
+			redirect_to,
+			max_uses: record.max_uses,
+			invocations: record.invocations,
+			created_at: record.created_at,
+			valid_for: record.valid_for,
+			management_token_hash: record.management_token_hash,
+			password_hash: record.password_hash,
+			redirect_type: record.redirect_type,
+			shared: record.shared,
+		})
+	}
+}
+
+/// Streams all links in the format, including expired ones that weren't cleaned up yet.
+/// The links are read page by page, so the whole database never has to fit into memory.
+pub fn export(storage: Arc<dyn Storage>, format: Format) -> impl Stream<Item = Result<Bytes, ShortyError>> {
+	let encoder = RecordEncoder { format, header_written: false };
+
+	// The state holds the ID of the last exported link, or `None` once all links were exported.
+	stream::try_unfold((storage, encoder, Some(String::new())), |(storage, mut encoder, after)| async move {
+		let Some(after) = after else {
+			return Ok(None);
+		};
+
+		let links = storage.links_after(after.as_str(), EXPORT_PAGE_SIZE).await?;
+		let bytes = encoder.encode(&links);
+
+		let next = if links.len() < usize::try_from(EXPORT_PAGE_SIZE).unwrap_or_default() {
+			None
+		} else {
+			links.last().map(|link| link.id.clone())
+		};
+
+
+		Ok(Some((bytes, (storage, encoder, next))))
+	})
+}
+
+/// Turns links into the bytes of the export format.
+struct RecordEncoder {
+	format: Format,
+	header_written: bool,
+}
+
+impl RecordEncoder {
+	fn encode(&mut self, links: &[Link]) -> Bytes {
+		let mut out = Vec::new();
+
+		match self.format {
+			Format::Jsonl => {
+				for link in links {
+					serde_json::to_writer(&mut out, &LinkRecord::from(link)).expect("Links can always be serialized.");
+					out.push(b'\n');
+				}
+			},
+			Format::Csv => {
+				let mut writer = csv::WriterBuilder::new()
+					.has_headers(false)
+					.from_writer(&mut out);
+
+				// The header is written explicitly, so an empty export still has one.
+				if !self.header_written {
+					writer.write_record(CSV_HEADER).expect("Links can always be serialized.");
+					self.header_written = true;
+				}
+
+				for link in links {
+					writer.serialize(LinkRecord::from(link)).expect("Links can always be serialized.");
+				}
+
+				writer.flush().expect("Links can always be serialized.");
+			},
+		}
+
+
+		Bytes::from(out)
+	}
+}
+
+/// A record of the input, together with the line it starts on.
+pub type DecodedRecord = (usize, Result<LinkRecord, String>);
+
+/// Splits the input of an import into records, while it arrives in chunks.
+pub struct RecordDecoder {
+	format: Format,
+	buffer: Vec<u8>,
+	/// The line the first record in the buffer starts on.
+	line: usize,
+	headers: Option<csv::StringRecord>,
+}
+
+impl RecordDecoder {
+	#[must_use]
+	pub fn new(format: Format) -> Self {
+		Self {
+			format,
+			buffer: Vec::new(),
+			line: 1,
+			headers: None,
+		}
+	}
+
+	/// Adds the next chunk of the input and returns the records that are complete now.
+	pub fn push(&mut self, chunk: &[u8]) -> Vec<DecodedRecord> {
+		self.buffer.extend_from_slice(chunk);
+
+		// Line breaks inside of quoted CSV fields belong to the field.
+		let mut in_quotes = false;
+		let mut ends = Vec::new();
+		for (i, byte) in self.buffer.iter().enumerate() {
+			match byte {
+				b'"' if self.format == Format::Csv => in_quotes = !in_quotes,
+				b'\n' if !in_quotes => ends.push(i),
+				_ => {},
+			}
+		}
+
+		let Some(&last_end) = ends.last() else {
+			return Vec::new();
+		};
+
+		let complete: Vec<u8> = self.buffer.drain(..=last_end).collect();
+		let mut records = Vec::new();
+		let mut start = 0;
+
+		for end in ends {
+			let raw = &complete[start..end];
+			let line = self.line;
+			self.line += raw.iter().filter(|&&byte| byte == b'\n').count() + 1;
+			start = end + 1;
+
+			if let Some(record) = self.decode(raw) {
+				records.push((line, record));
+			}
+		}
+
+
+		records
+	}
+
+	/// Returns the last record, which doesn't have to end with a line break.
+	pub fn finish(mut self) -> Option<DecodedRecord> {
+		let raw = std::mem::take(&mut self.buffer);
+		let line = self.line;
+
+
+		self.decode(raw.as_slice()).map(|record| (line, record))
+	}
+
+	/// Decodes a single record, returns `None` for empty lines and the CSV header.
+	fn decode(&mut self, raw: &[u8]) -> Option<Result<LinkRecord, String>> {
+		if raw.iter().all(u8::is_ascii_whitespace) {
+			return None;
+		}
+
+		match self.format {
+			Format::Jsonl => Some(serde_json::from_slice(raw).map_err(|why| why.to_string())),
+			Format::Csv => {
+				let mut reader = csv::ReaderBuilder::new()
+					.has_headers(false)
+					.from_reader(raw);
+
+				let mut row = csv::StringRecord::new();
+				match reader.read_record(&mut row) {
+					Ok(true) => {},
+					Ok(false) => return None,
+					Err(why) => return Some(Err(why.to_string())),
+				}
+
+				let Some(headers) = &self.headers else {
+					self.headers = Some(row);
+					return None;
+				};
+
+
+				Some(row.deserialize(Some(headers)).map_err(|why| why.to_string()))
+			},
+		}
+	}
+}
+
+/// How an import went.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ImportSummary {
+	/// How many links were imported without a conflict.
+	pub imported: u64,
+	/// How many existing links were replaced by imported ones.
+	pub overwritten: u64,
+	/// How many imported links were dropped because their ID was used already.
+	pub skipped: u64,
+	/// The links that were imported with a different ID.
+	pub renamed: Vec<RenamedLink>,
+	/// The records that couldn't be imported.
+	pub failed: Vec<ImportFailure>,
+}
+
+/// A link that was imported with a different ID, because its own was used already.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RenamedLink {
+	pub from: String,
+	pub to: String,
+}
+
+/// A record that couldn't be imported.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportFailure {
+	/// The line of the input the record starts on.
+	pub line: usize,
+	pub message: String,
+}
+
+/// Inserts decoded records into the database, resolving conflicts with the strategy.
+pub struct Importer {
+	storage: Arc<dyn Storage>,
+	conflict: ConflictStrategy,
+	summary: ImportSummary,
+}
+
+impl Importer {
+	#[must_use]
+	pub fn new(storage: Arc<dyn Storage>, conflict: ConflictStrategy) -> Self {
+		Self {
+			storage,
+			conflict,
+			summary: ImportSummary::default(),
+		}
+	}
+
+	/// Imports a record. Records that are invalid or can't be renamed are counted as failed.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn import(&mut self, (line, record): DecodedRecord) -> Result<(), ShortyError> {
+		let mut link = match record.and_then(Link::try_from) {
+			Ok(link) => link,
+			Err(message) => {
+				self.summary.failed.push(ImportFailure { line, message });
+				return Ok(());
+			},
+		};
+
+		if self.storage.insert_link_if_absent(&link).await? {
+			self.summary.imported += 1;
+			return Ok(());
+		}
+
+		match self.conflict {
+			ConflictStrategy::Skip => self.summary.skipped += 1,
+			ConflictStrategy::Overwrite => {
+				self.storage.insert_link(&link).await?;
+				self.summary.overwritten += 1;
+			},
+			ConflictStrategy::Rename => {
+				let original = link.id.clone();
+
+				for suffix in 2..=MAX_RENAME_ATTEMPTS + 1 {
+					link.id = match Link::validate_custom_id(format!("{original}-{suffix}").as_str()) {
+						Ok(id) => id,
+						Err(why) => {
+							self.summary.failed.push(ImportFailure { line, message: why.to_string() });
+							return Ok(());
+						},
+					};
+
+					if self.storage.insert_link_if_absent(&link).await? {
+						self.summary.renamed.push(RenamedLink { from: original, to: link.id });
+						return Ok(());
+					}
+				}
+
+				self.summary.failed.push(ImportFailure {
+					line,
+					message: format!("No free ID was found for {original}."),
+				});
+			},
+		}
+
+
+		Ok(())
+	}
+
+	#[must_use]
+	pub fn finish(self) -> ImportSummary {
+		self.summary
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use actix_web::web::Bytes;
+	use futures_util::TryStreamExt;
+
+	use crate::link::Link;
+	use crate::storage::sqlite::SqliteStorage;
+	use crate::storage::Storage;
+	use crate::util::time_now;
+
+	use super::{ConflictStrategy, export, Format, Importer, ImportSummary, RecordDecoder};
+
+	fn link(id: &str, redirect_to: &str, invocations: i64, created_at: i64, valid_for: i64) -> Link {
+		Link {
+			id: id.to_owned(),
+			redirect_to: redirect_to.to_owned(),
+			max_uses: 10,
+			invocations,
+			created_at,
+			valid_for,
+			management_token_hash: Some("token-hash".to_owned()),
+			password_hash: None,
+			redirect_type: 308,
+			shared: false,
+		}
+	}
+
+	async fn storage_with(links: &[Link]) -> Arc<dyn Storage> {
+		let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::in_memory().await.unwrap());
+		storage.migrate().await.unwrap();
+
+		for link in links {
+			storage.insert_link(link).await.unwrap();
+		}
+
+
+		storage
+	}
+
+	async fn round_trip(format: Format, conflict: ConflictStrategy, source: &[Link], target: Arc<dyn Storage>) -> ImportSummary {
+		let exported: Vec<Bytes> = export(storage_with(source).await, format)
+			.try_collect()
+			.await
+			.unwrap();
+
+		let mut decoder = RecordDecoder::new(format);
+		let mut records = decoder.push(exported.concat().as_slice());
+		records.extend(decoder.finish());
+
+		let mut importer = Importer::new(target, conflict);
+		for record in records {
+			importer.import(record).await.unwrap();
+		}
+
+
+		importer.finish()
+	}
+
+	async fn assert_imported(storage: &dyn Storage, id: &str, original: &Link) {
+		let imported = storage.get_link(id).await.unwrap().unwrap();
+
+		assert_eq!(imported.redirect_to, original.redirect_to);
+		assert_eq!(imported.max_uses, original.max_uses);
+		assert_eq!(imported.invocations, original.invocations);
+		assert_eq!(imported.created_at, original.created_at);
+		assert_eq!(imported.valid_for, original.valid_for);
+		assert_eq!(imported.management_token_hash, original.management_token_hash);
+		assert_eq!(imported.password_hash, original.password_hash);
+		assert_eq!(imported.redirect_type, original.redirect_type);
+		assert_eq!(imported.shared, original.shared);
+	}
+
+	#[actix_web::test]
+	async fn export_and_import_round_trip() {
+		let now = time_now();
+		let source = [
+			link("taken", "https://example.com/taken", 3, now - 5000, 0),
+			link("free", "https://example.com/free", 7, now - 1000, 60_000),
+		];
+		let existing = link("taken", "https://example.com/existing", 0, now, 0);
+
+		for format in [Format::Jsonl, Format::Csv] {
+			let target = storage_with(std::slice::from_ref(&existing)).await;
+			let summary = round_trip(format, ConflictStrategy::Skip, &source, target.clone()).await;
+			assert_eq!((summary.imported, summary.skipped), (1, 1), "{format:?}");
+			assert_imported(target.as_ref(), "taken", &existing).await;
+			assert_imported(target.as_ref(), "free", &source[1]).await;
+
+			let target = storage_with(std::slice::from_ref(&existing)).await;
+			let summary = round_trip(format, ConflictStrategy::Overwrite, &source, target.clone()).await;
+			assert_eq!((summary.imported, summary.overwritten), (1, 1), "{format:?}");
+			assert_imported(target.as_ref(), "taken", &source[0]).await;
+			assert_imported(target.as_ref(), "free", &source[1]).await;
+
+			let target = storage_with(std::slice::from_ref(&existing)).await;
+			let summary = round_trip(format, ConflictStrategy::Rename, &source, target.clone()).await;
+			assert_eq!(summary.imported, 1, "{format:?}");
+			assert_eq!(summary.renamed.len(), 1, "{format:?}");
+			assert_eq!(summary.renamed[0].to, "taken-2", "{format:?}");
+			assert_imported(target.as_ref(), "taken", &existing).await;
+			assert_imported(target.as_ref(), "taken-2", &source[0]).await;
+			assert_imported(target.as_ref(), "free", &source[1]).await;
+
+			assert!(summary.failed.is_empty(), "{format:?}: {:?}", summary.failed);
+		}
+	}
+}
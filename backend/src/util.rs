@@ -1,17 +1,25 @@
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::http::header::{Accept, AUTHORIZATION, Header, Quality, X_FORWARDED_FOR};
 use actix_web::http::Uri;
+use actix_web::HttpRequest;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::SaltString;
 use base64::{engine, Engine};
 use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
 use chrono::Local;
 use rand::RngCore;
-use sqlx::{Pool, Sqlite};
-use tracing::error;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
 
-use crate::link::Link;
+use crate::CONFIG;
 use crate::ShortyError;
-
-/// How many random bytes should be generated for the IDs.
-/// They are then URL encoded, so a `URL_SIZE` of 4 corresponds to 6 chars without padding.
-const URL_SIZE: usize = 4;
+use crate::link::Link;
+use crate::metrics::METRICS;
+use crate::storage::{InsertOutcome, StorageTransaction};
+use crate::validation::check_id;
 
 const BASE64_ENGINE: GeneralPurpose = engine::GeneralPurpose::new(
 	&base64::alphabet::URL_SAFE,
@@ -20,21 +28,17 @@ const BASE64_ENGINE: GeneralPurpose = engine::GeneralPurpose::new(
 		.with_decode_padding_mode(DecodePaddingMode::Indifferent),
 );
 
+/// How often an ID of one length is tried before the length is increased.
 const RANDOM_ID_RETRIES: u32 = 3;
 
-/// Checks if the URL starts with `http` or `https`.
-/// If it doesn't it prepends `http`.
-/// We have to do this because otherwise the browser will assume we are redirecting
-/// to a subpage on the same domain.
-#[must_use]
-pub fn ensure_http_prefix(url: String) -> String {
-	if url.starts_with("http://") || url.starts_with("https://") {
-		return url;
-	}
+/// The length random IDs can grow to when there are collisions.
+const MAX_RANDOM_ID_LENGTH: usize = 64;
 
+/// The length random IDs grew to because of collisions, so they don't have to grow again every time.
+static GROWN_ID_LENGTH: AtomicUsize = AtomicUsize::new(0);
 
-	format!("http://{url}")
-}
+/// How many random bytes a management token consists of.
+const MANAGEMENT_TOKEN_SIZE: usize = 32;
 
 /// This function replaces illegal URL chars with ones that can be used in urls.
 /// Currently it just replaces spaces with underscores, additions might happen in the future.
@@ -42,39 +46,179 @@ pub fn replace_illegal_url_chars(s: impl AsRef<str>) -> String {
 	s.as_ref().replace([' ', '/'], "_")
 }
 
-/// Generates some random chars.
-/// Used for the random ID.
-/// We generate a few random bytes (How many is defined by `URL_SIZE`).
+/// Escapes the wildcard characters of SQL `LIKE` patterns, using `\` as the escape character.
 #[must_use]
-pub fn generate_random_chars() -> String {
-	let mut random_bytes: [u8; URL_SIZE] = [0; URL_SIZE];
+pub fn escape_like_pattern(s: &str) -> String {
+	s.replace('\\', "\\\\")
+		.replace('%', "\\%")
+		.replace('_', "\\_")
+}
+
+/// Gives the link a random ID with the configured alphabet and inserts it, if the ID is free.
+/// IDs of expired links count as free, those links get replaced.
+/// Starts out with the configured `id_length`, after [`RANDOM_ID_RETRIES`] collisions in a row
+/// the length is increased by one, as the IDs of that length are getting scarce.
+/// Later IDs start out with the increased length.
+///
+/// # Errors
+///
+/// Errors if the IDs keep colliding up to a length of [`MAX_RANDOM_ID_LENGTH`].
+///
+/// Errors if there is some problem communicating with the database.
+pub async fn insert_with_random_id(
+	link: &mut Link,
+	transaction: &mut dyn StorageTransaction,
+) -> Result<(), ShortyError> {
+	// Empty IDs would collide with the index page.
+	let config = CONFIG.load();
+	let min_length = config.id_length.max(GROWN_ID_LENGTH.load(Ordering::Relaxed)).max(1);
+
+	for length in min_length..=MAX_RANDOM_ID_LENGTH.max(min_length) {
+		for _ in 0..RANDOM_ID_RETRIES {
+			link.id = config.id_alphabet.generate(length);
+
+			if check_id(link.id.as_str()).is_err() {
+				continue;
+			}
+
+			let outcome = transaction.insert_link_if_free(link, link.created_at).await?;
+			METRICS.random_id(outcome);
+
+			if outcome != InsertOutcome::Occupied {
+				return Ok(());
+			}
+		}
+
+		warn!("Tried {RANDOM_ID_RETRIES} times to generate a random ID of length {length}, increasing the length.");
+		GROWN_ID_LENGTH.fetch_max(length + 1, Ordering::Relaxed);
+	}
+	error!("Failed to generate a random ID up to a length of {MAX_RANDOM_ID_LENGTH}!");
+
+
+	Err(ShortyError::RandomIDMaxRetriesExceeded)
+}
+
+/// Generates a new secret management token.
+/// The token is handed to the creator of a link once and is never stored in plain text,
+/// see [`hash_management_token`].
+#[must_use]
+pub fn generate_management_token() -> String {
+	let mut random_bytes: [u8; MANAGEMENT_TOKEN_SIZE] = [0; MANAGEMENT_TOKEN_SIZE];
 	rand::thread_rng().fill_bytes(&mut random_bytes);
 
 
 	BASE64_ENGINE.encode(random_bytes)
 }
 
-/// Calls [`generate_random_chars`] and looks if the id already exists in the database.
-/// Gives up after [`RANDOM_ID_RETRIES`] tries.
-/// Currently, if it generates a random ID and a link with that ID exists in the Database, it
-/// considers the ID as "occupied", even if the link in question is already expired.
+/// The SHA-256 digest of a secret token.
+/// Comparing digests instead of the tokens doesn't leak how much of a token matched through the time it takes.
+#[must_use]
+pub fn digest_token(token: &str) -> [u8; 32] {
+	Sha256::digest(token.as_bytes()).into()
+}
+
+/// Hashes a management token for storage in the database.
+/// The tokens are long random strings, so a plain SHA-256 is sufficient here.
+#[must_use]
+pub fn hash_management_token(token: &str) -> String {
+	BASE64_ENGINE.encode(digest_token(token))
+}
+
+/// Hashes a link password with Argon2 and a random salt for storage in the database.
+/// Unlike management tokens, passwords are chosen by people and need a slow hash.
 ///
 /// # Errors
 ///
-/// Errors if it fails to generate a valid link in [`RANDOM_ID_RETRIES`] tries.
-///
-/// Errors if there is some problem communicating with the database.
-pub async fn get_random_id(pool: &Pool<Sqlite>) -> Result<String, ShortyError> {
-	for _ in 0..RANDOM_ID_RETRIES {
-		let random_chars = generate_random_chars();
-		if !Link::link_exists(random_chars.as_str(), pool).await? {
-			return Ok(random_chars);
+/// Errors if the password couldn't be hashed.
+pub fn hash_password(password: &str) -> Result<String, ShortyError> {
+	let salt = SaltString::generate(&mut OsRng);
+
+	let hash = Argon2::default()
+		.hash_password(password.as_bytes(), &salt)
+		.map_err(ShortyError::PasswordHash)?;
+
+
+	Ok(hash.to_string())
+}
+
+/// Checks the password against a hash created by [`hash_password`].
+#[must_use]
+pub fn verify_password(password: &str, hash: &str) -> bool {
+	PasswordHash::new(hash)
+		.and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+		.is_ok()
+}
+
+/// Escapes the characters that have a special meaning in HTML.
+#[must_use]
+pub fn escape_html(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#x27;"),
+			c => escaped.push(c),
 		}
 	}
-	error!("Tried {} times to generate a random ID, but failed!", RANDOM_ID_RETRIES);
 
 
-	Err(ShortyError::RandomIDMaxRetriesExceeded)
+	escaped
+}
+
+/// The IP address of the client that made the request.
+/// The `X-Forwarded-For` header is only respected if `trust_x_forwarded_for` is set,
+/// in which case the address the reverse proxy appended last is used.
+#[must_use]
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+	if CONFIG.load().trust_x_forwarded_for {
+		let forwarded = req.headers()
+			.get(X_FORWARDED_FOR)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.rsplit(',').next())
+			.and_then(|ip| ip.trim().parse().ok());
+
+		if forwarded.is_some() {
+			return forwarded;
+		}
+	}
+
+
+	req.peer_addr().map(|addr| addr.ip())
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header, if present.
+#[must_use]
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+	req.headers()
+		.get(AUTHORIZATION)?
+		.to_str()
+		.ok()?
+		.strip_prefix("Bearer ")
+		.map(str::trim)
+}
+
+/// Checks if the client prefers a JSON response over plain text, according to its `Accept` header.
+/// Plain text is assumed if the header is missing or both are equally acceptable.
+#[must_use]
+pub fn prefers_json(req: &HttpRequest) -> bool {
+	let Ok(accept) = Accept::parse(req) else {
+		return false;
+	};
+
+	// The quality of the most acceptable of the types, types with a quality of 0 aren't acceptable at all.
+	let quality = |types: &[&str]| {
+		accept.iter()
+			.filter(|item| item.quality > Quality::ZERO && types.contains(&item.item.essence_str()))
+			.map(|item| item.quality)
+			.max()
+	};
+
+
+	quality(&["application/json"]) > quality(&["text/plain", "text/*", "*/*"])
 }
 
 /// If the URI is longer than 0 chars, it contains a `/` char at the first position.
@@ -96,3 +240,37 @@ pub fn uri_to_url(uri: &Uri) -> String {
 pub fn time_now() -> i64 {
 	Local::now().timestamp_millis()
 }
+
+#[cfg(test)]
+mod tests {
+	use actix_web::http::header::ACCEPT;
+	use actix_web::test::TestRequest;
+
+	use super::prefers_json;
+
+	fn prefers_json_for(accept: &str) -> bool {
+		prefers_json(&TestRequest::default().insert_header((ACCEPT, accept)).to_http_request())
+	}
+
+	#[test]
+	fn plain_text_wins_ties() {
+		assert!(!prefers_json_for("application/json, text/plain"));
+		assert!(!prefers_json_for("text/plain, application/json"));
+		assert!(!prefers_json_for("application/json;q=0.5, */*;q=0.5"));
+	}
+
+	#[test]
+	fn json_wins_when_preferred() {
+		assert!(prefers_json_for("application/json"));
+		assert!(prefers_json_for("text/plain;q=0.9, application/json"));
+		assert!(prefers_json_for("application/json, */*;q=0.8"));
+	}
+
+	#[test]
+	fn plain_text_without_json() {
+		assert!(!prefers_json(&TestRequest::default().to_http_request()));
+		assert!(!prefers_json_for("*/*"));
+		assert!(!prefers_json_for("text/html"));
+		assert!(!prefers_json_for("application/json;q=0, text/plain;q=0.1"));
+	}
+}
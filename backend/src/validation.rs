@@ -0,0 +1,154 @@
+use url::Url;
+
+use crate::error::ShortyError;
+
+/// The schemes links are allowed to redirect to.
+const ALLOWED_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// The paths of the fixed routes, links with these IDs would be shadowed by them.
+const RESERVED_IDS: [&str; 13] = [
+	"admin", "assets", "config", "custom", "documentation", "favicon.ico", "healthz",
+	"info", "manage", "metrics", "readyz", "stats", "unlock",
+];
+
+/// Checks that links with the ID can be reached, instead of a fixed route of the server.
+/// IDs ending with `+` would show the preview of the link without it.
+///
+/// # Errors
+///
+/// Errors if the ID is reserved.
+pub fn check_id(id: &str) -> Result<(), ShortyError> {
+	if RESERVED_IDS.contains(&id) || id.ends_with('+') {
+		return Err(ShortyError::ReservedID(id.to_owned()));
+	}
+
+
+	Ok(())
+}
+
+/// Parses the link and brings it into its normalized form.
+/// Links without a scheme get `http://` prepended, links with any scheme other than
+/// `http` or `https` are rejected.
+/// The host gets lowercased and converted to punycode, default ports and `.` segments
+/// in the path are removed and characters not allowed in URLs are percent encoded.
+///
+/// # Errors
+///
+/// Errors if the link has a disallowed scheme or is not a valid URL.
+pub fn normalize_link(link: &str) -> Result<String, ShortyError> {
+	let link = link.trim();
+
+	let url = match explicit_scheme(link) {
+		Some(scheme) if !ALLOWED_SCHEMES.contains(&scheme.to_lowercase().as_str()) => {
+			return Err(ShortyError::DisallowedScheme(scheme.to_owned()));
+		},
+		Some(_) => Url::parse(link),
+		None => Url::parse(format!("http://{link}").as_str()),
+	}.map_err(|why| ShortyError::InvalidLink(why.to_string()))?;
+
+
+	Ok(url.into())
+}
+
+/// Returns the scheme the link starts with, if it has one.
+/// Something like `localhost:8080` is a host with a port and not the scheme `localhost`.
+fn explicit_scheme(link: &str) -> Option<&str> {
+	let (scheme, rest) = link.split_once(':')?;
+
+	let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+		&& scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+
+	let port = rest.split(['/', '?', '#']).next().unwrap_or_default();
+	let is_port = !port.is_empty() && port.chars().all(|c| c.is_ascii_digit());
+
+
+	(is_scheme && !is_port).then_some(scheme)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeSet;
+
+	use crate::error::ShortyError;
+
+	use super::{explicit_scheme, normalize_link, RESERVED_IDS};
+
+	/// Where the routes are registered, along with how their paths start.
+	const ROUTE_SOURCES: [&str; 2] = [include_str!("endpoints.rs"), include_str!("main.rs")];
+	const ROUTE_MARKERS: [&str; 6] = ["#[get(\"/", "#[post(\"/", "#[patch(\"/", "#[delete(\"/", "web::scope(\"/", "SwaggerUi::new(\"/"];
+
+	/// The first path segments of the fixed routes, skipping the routes that start with a placeholder.
+	fn route_prefixes() -> BTreeSet<&'static str> {
+		ROUTE_SOURCES.iter()
+			.flat_map(|source| source.lines())
+			.filter_map(|line| {
+				let line = line.trim();
+				ROUTE_MARKERS.iter().find_map(|marker| line.split_once(marker).map(|(_, path)| path))
+			})
+			.map(|path| path.split(['/', '"', '{']).next().unwrap_or_default())
+			.filter(|prefix| !prefix.is_empty())
+			.collect()
+	}
+
+	#[test]
+	fn links_are_normalized() {
+		let cases = [
+			("example.com", "http://example.com/"),
+			("  https://example.com/a  ", "https://example.com/a"),
+			("HTTPS://Example.COM/Path", "https://example.com/Path"),
+			("https://bücher.example/", "https://xn--bcher-kva.example/"),
+			("https://example.com:443/a", "https://example.com/a"),
+			("http://example.com:80/a", "http://example.com/a"),
+			("https://example.com:80/a", "https://example.com:80/a"),
+			("localhost:8080/a", "http://localhost:8080/a"),
+			("https://example.com/a/./b/../c", "https://example.com/a/c"),
+			("https://example.com/a b", "https://example.com/a%20b"),
+		];
+
+		for (link, normalized) in cases {
+			assert_eq!(normalize_link(link).unwrap(), normalized, "{link}");
+		}
+	}
+
+	#[test]
+	fn disallowed_schemes_are_rejected() {
+		let cases = [
+			("javascript:alert(1)", "javascript"),
+			("JavaScript:alert(1)", "JavaScript"),
+			("ftp://example.com/file", "ftp"),
+			("data:text/html,hi", "data"),
+			("mailto:someone@example.com", "mailto"),
+		];
+
+		for (link, scheme) in cases {
+			assert!(
+				matches!(normalize_link(link), Err(ShortyError::DisallowedScheme(rejected)) if rejected == scheme),
+				"{link}",
+			);
+		}
+	}
+
+	#[test]
+	fn ports_are_not_schemes() {
+		let cases = [
+			("https://example.com", Some("https")),
+			("javascript:alert(1)", Some("javascript")),
+			("a+b.c-d://example.com", Some("a+b.c-d")),
+			("example.com", None),
+			("localhost:8080", None),
+			("localhost:8080/a", None),
+			("example.com:443?q", None),
+			("1password:x", None),
+			("ex ample:x", None),
+		];
+
+		for (link, scheme) in cases {
+			assert_eq!(explicit_scheme(link), scheme, "{link}");
+		}
+	}
+
+	#[test]
+	fn reserved_ids_are_the_fixed_routes() {
+		assert_eq!(route_prefixes(), RESERVED_IDS.into_iter().collect());
+	}
+}
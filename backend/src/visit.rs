@@ -0,0 +1,231 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use actix_web::http::header::{REFERER, USER_AGENT};
+use actix_web::HttpRequest;
+use maxminddb::{geoip2, Reader};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::CONFIG;
+use crate::error::ShortyError;
+use crate::storage::{Storage, VisitField};
+use crate::util::{client_ip, time_now};
+
+/// How many entries the referrer, user agent and country breakdowns contain at most.
+const MAX_BREAKDOWN_ENTRIES: i64 = 25;
+
+/// A single redirect that was served for a link.
+#[derive(Debug, Clone)]
+pub struct Visit {
+	pub link_id: String,
+	pub visited_at: i64,
+	pub referrer: Option<String>,
+	pub user_agent_family: Option<String>,
+	pub country: Option<String>,
+}
+
+/// The size of the time buckets the visits get grouped into.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsInterval {
+	Hour,
+	#[default]
+	Day,
+	Week,
+}
+
+impl StatsInterval {
+	/// Length of the interval in milliseconds.
+	#[must_use]
+	pub const fn millis(self) -> i64 {
+		match self {
+			StatsInterval::Hour => 60 * 60 * 1000,
+			StatsInterval::Day => 24 * 60 * 60 * 1000,
+			StatsInterval::Week => 7 * 24 * 60 * 60 * 1000,
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+	/// The size of the time buckets, defaults to `day`.
+	#[serde(default)]
+	#[param(inline)]
+	pub interval: StatsInterval,
+}
+
+/// Amount of visits in the time bucket starting at `start`.
+#[derive(Debug, Clone, Serialize, ToSchema, sqlx::FromRow)]
+pub struct VisitBucket {
+	/// Start of the bucket in milliseconds, buckets are aligned to UTC.
+	pub start: i64,
+	pub visits: i64,
+}
+
+/// Amount of visits with a certain referrer, user agent or country.
+#[derive(Debug, Clone, Serialize, ToSchema, sqlx::FromRow)]
+pub struct VisitCount {
+	/// The referrer, user agent family or country, absent if it was unknown.
+	pub value: Option<String>,
+	pub visits: i64,
+}
+
+/// Statistics about the visits of a link.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(example = json!({
+	"link_id": "search",
+	"interval": "day",
+	"total": 3,
+	"buckets": [{"start": 1_697_414_400_000_i64, "visits": 1}, {"start": 1_697_500_800_000_i64, "visits": 2}],
+	"referrers": [{"value": "https://example.com/", "visits": 2}, {"value": null, "visits": 1}],
+	"user_agents": [{"value": "Firefox", "visits": 3}],
+	"countries": [{"value": "DE", "visits": 3}]
+}))]
+pub struct VisitStats {
+	pub link_id: String,
+	pub interval: StatsInterval,
+	pub total: i64,
+	/// Buckets without visits are left out.
+	pub buckets: Vec<VisitBucket>,
+	/// The most common referrers.
+	pub referrers: Vec<VisitCount>,
+	/// The most common user agent families.
+	pub user_agents: Vec<VisitCount>,
+	/// The most common countries as ISO codes.
+	pub countries: Vec<VisitCount>,
+}
+
+impl Visit {
+	/// Gathers the information about a visit from the request that followed the link.
+	#[must_use]
+	pub fn from_request(link_id: &str, req: &HttpRequest, geoip: Option<&Reader<Vec<u8>>>) -> Self {
+		let header = |name| {
+			req.headers()
+				.get(name)
+				.and_then(|value| value.to_str().ok())
+		};
+
+		let referrer = header(REFERER)
+			.map(|referrer| referrer.chars().take(CONFIG.load().max_link_length).collect());
+		let user_agent_family = header(USER_AGENT)
+			.map(|user_agent| user_agent_family(user_agent).to_owned());
+		let country = geoip
+			.zip(client_ip(req))
+			.and_then(|(reader, ip)| lookup_country(reader, ip));
+
+		Self {
+			link_id: link_id.to_owned(),
+			visited_at: time_now(),
+			referrer,
+			user_agent_family,
+			country,
+		}
+	}
+}
+
+pub struct VisitStore {
+	storage: Arc<dyn Storage>,
+	geoip: Option<Reader<Vec<u8>>>,
+}
+
+impl VisitStore {
+	#[must_use]
+	pub fn new(storage: Arc<dyn Storage>, geoip: Option<Reader<Vec<u8>>>) -> Self {
+		Self { storage, geoip }
+	}
+
+	/// Records a visit of the link with the provided ID.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn record(&self, link_id: &str, req: &HttpRequest) -> Result<(), ShortyError> {
+		let visit = Visit::from_request(link_id, req, self.geoip.as_ref());
+		debug!("Recording visit {visit:?}");
+
+
+		self.storage.insert_visit(&visit).await
+	}
+
+	/// Collects the statistics for the link with the provided ID.
+	///
+	/// # Errors
+	///
+	/// Errors if there is some problem communicating with the database.
+	pub async fn stats(&self, link_id: &str, interval: StatsInterval) -> Result<VisitStats, ShortyError> {
+		let buckets = self.storage.visit_buckets(link_id, interval.millis()).await?;
+		let referrers = self.storage
+			.visit_breakdown(link_id, VisitField::Referrer, MAX_BREAKDOWN_ENTRIES)
+			.await?;
+		let user_agents = self.storage
+			.visit_breakdown(link_id, VisitField::UserAgentFamily, MAX_BREAKDOWN_ENTRIES)
+			.await?;
+		let countries = self.storage
+			.visit_breakdown(link_id, VisitField::Country, MAX_BREAKDOWN_ENTRIES)
+			.await?;
+
+		let total = buckets.iter().map(|bucket| bucket.visits).sum();
+
+
+		Ok(VisitStats {
+			link_id: link_id.to_owned(),
+			interval,
+			total,
+			buckets,
+			referrers,
+			user_agents,
+			countries,
+		})
+	}
+}
+
+/// Opens the GeoIP database configured by `geoip_database`, if there is one.
+///
+/// # Panics
+///
+/// Panics if the configured database can't be opened.
+#[must_use]
+pub fn open_geoip_database() -> Option<Reader<Vec<u8>>> {
+	CONFIG.load().geoip_database.as_ref().map(|path| {
+		Reader::open_readfile(path).expect("Failed to open the GeoIP database.")
+	})
+}
+
+/// Looks up the ISO code of the country the IP address belongs to.
+fn lookup_country(reader: &Reader<Vec<u8>>, ip: IpAddr) -> Option<String> {
+	let country: geoip2::Country = reader.lookup(ip).ok()?;
+
+
+	country.country?.iso_code.map(str::to_owned)
+}
+
+/// Roughly determines the browser or tool a user agent string belongs to.
+/// The order of the checks matters, since most browsers include the names of others.
+#[must_use]
+pub fn user_agent_family(user_agent: &str) -> &'static str {
+	let lowercase = user_agent.to_lowercase();
+	let contains = |needle: &str| lowercase.contains(needle);
+
+	if contains("bot") || contains("spider") || contains("crawl") {
+		"Bot"
+	} else if contains("edg/") || contains("edga/") || contains("edgios/") {
+		"Edge"
+	} else if contains("opr/") || contains("opera") {
+		"Opera"
+	} else if contains("firefox/") || contains("fxios/") {
+		"Firefox"
+	} else if contains("chrome/") || contains("crios/") || contains("chromium/") {
+		"Chrome"
+	} else if contains("safari/") {
+		"Safari"
+	} else if contains("curl/") {
+		"curl"
+	} else if contains("wget/") {
+		"Wget"
+	} else {
+		"Other"
+	}
+}
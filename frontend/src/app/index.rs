@@ -6,9 +6,10 @@ use crate::{
     components::{
         footer::Footer,
         link_form::LinkForm,
+        link_preview::LinkPreview,
         message_box::{Message, MessageBox},
     },
-    util::AsClasses,
+    util::{preview_id, AsClasses},
     ACCENT_COLOR,
     BACKGROUND_COLOR,
     FONT_COLOR,
@@ -128,7 +129,11 @@ impl Component for Index {
                     <MessageBox manage_messages={ manage_messages.clone() } messages={ self.messages.clone() }/>
                     <div class={ OUTER_CONTAINER.as_classes() }>
                         <div class={ INNER_CONTAINER.as_classes() }>
-                            <LinkForm { manage_messages } />
+                            if let Some(id) = preview_id() {
+                                <LinkPreview { id } { manage_messages } />
+                            } else {
+                                <LinkForm { manage_messages } />
+                            }
                         </div>
                     </div>
                     <Footer/>
@@ -24,7 +24,11 @@ use super::{
 use crate::{
     app::index::IndexMessage,
     endpoint,
-    types::{error::RequestError, link_config::LinkConfig, ServerConfig},
+    types::{
+        error::{BackendErrorCode, RequestError},
+        link_config::LinkConfig,
+        ServerConfig,
+    },
     util::{generate_id, AsClasses},
     INPUT_WIDTH,
 };
@@ -86,7 +90,7 @@ async fn make_request(
     let text = response
         .text()
         .await
-        .expect("Expected a text/plain response");
+        .expect("Expected a text/plain or json response");
 
     debug!(
         "Received: {:#?}\n from /custom with code {}",
@@ -95,19 +99,19 @@ async fn make_request(
     );
 
     if status.is_success() {
-        Ok(AttrValue::from(text))
-    } else {
-        Err(match status.as_u16() {
-            // TODO use concrete backend error enum to match
-            400 => RequestError::Backend400,
-            409 => RequestError::IdInUse {
-                id: link_config
-                    .id
-                    .expect("Server returned an in use id, even though no custom id was provided"),
-            },
-            code => panic!("Unexpected return code: {}", code),
-        })
+        return Ok(AttrValue::from(text));
     }
+
+    let error = RequestError::from_response(status.as_u16(), &text);
+
+    Err(match (error, link_config.id) {
+        (RequestError::Backend { error }, Some(id))
+            if error.code == BackendErrorCode::LinkConflict =>
+        {
+            RequestError::IdInUse { id }
+        },
+        (error, _) => error,
+    })
 }
 
 #[derive(Default, Clone)]
@@ -116,6 +120,8 @@ pub struct LinkFormRefs {
     pub advanced_mode: NodeRef,
     pub max_usage_input: NodeRef,
     pub custom_id_input: NodeRef,
+    pub password_input: NodeRef,
+    pub redirect_type_input: NodeRef,
     pub expiration_input: NodeRef,
     pub expiration_type: NodeRef,
 }
@@ -226,8 +232,12 @@ impl Component for LinkForm {
         let maxlength_id = self.server_config.as_ref().map(|c| AttrValue::from(format!("{}", c.max_link_length)));
         let maxlength_link = self.server_config.as_ref().map(|c| AttrValue::from(format!("{}", c.max_custom_id_length)));
         let max_uses = self.server_config.as_ref().map(|c| AttrValue::from(format!("{}", c.default_max_uses)));
+        let default_redirect_type = self.server_config.as_ref().map_or_else(
+            || "Server default".to_string(),
+            |c| format!("Server default ({})", c.default_redirect_type),
+        );
 
-        let ids = [generate_id(), generate_id(), generate_id()];
+        let ids = [generate_id(), generate_id(), generate_id(), generate_id(), generate_id()];
 
         // TODO remove code duplication
         html! {
@@ -249,6 +259,20 @@ impl Component for LinkForm {
                             <ExpirationInput id={ ids[2].clone() } toggle_ref={ self.refs.expiration_type.clone() } input_ref={ self.refs.expiration_input.clone() }/>
                         </div>
                     </div>
+                    <div class={ CONTAINER.as_classes() }>
+                        <label class={ LABEL.as_classes() } for={ ids[3].clone() }>{ "Password" }</label>
+                        <input id={ ids[3].clone() } class={ TEXT_INPUT.as_classes() } ref={ self.refs.password_input.clone() } type="password" autocomplete="new-password"/>
+                    </div>
+                    <div class={ CONTAINER.as_classes() }>
+                        <label class={ LABEL.as_classes() } for={ ids[4].clone() }>{ "Redirect type" }</label>
+                        <select id={ ids[4].clone() } class={ TEXT_INPUT.as_classes() } ref={ self.refs.redirect_type_input.clone() }>
+                            <option value="" selected=true>{ default_redirect_type }</option>
+                            <option value="307">{ "Temporary (307)" }</option>
+                            <option value="302">{ "Temporary (302)" }</option>
+                            <option value="308">{ "Permanent (308)" }</option>
+                            <option value="301">{ "Permanent (301)" }</option>
+                        </select>
+                    </div>
                 </AdvancedMode>
             </>
         }
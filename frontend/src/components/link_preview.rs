@@ -0,0 +1,182 @@
+use stylist::{css, StyleSource};
+use time::{macros::format_description, OffsetDateTime};
+use yew::{html, AttrValue, Callback, Component, Context, Html, Properties};
+
+use crate::{
+    app::index::IndexMessage,
+    endpoint,
+    types::{duration::Duration, error::RequestError, link_info::LinkInfo},
+    util::{try_get_local_offset, AsClasses},
+    ACCENT_COLOR,
+    FONT_COLOR,
+};
+
+thread_local! {
+    static HEADING: StyleSource = css!(r#"
+        margin: 0 0 4px;
+    "#);
+
+    static LABEL: StyleSource = css!(r#"
+        display: block;
+        font-size: 12px;
+        margin-top: 10px;
+        margin-bottom: 3px;
+    "#);
+
+    static VALUE: StyleSource = css!(r#"
+        word-break: break-all;
+        max-width: 500px;
+    "#);
+
+    // TODO make variable
+    static BUTTON: StyleSource = css!(r#"
+        display: inline-block;
+        margin-top: 20px;
+        background-color: ${ac};
+        color: ${fc};
+        padding: 8px;
+        border-radius: 10px;
+        font-size: 18px;
+        text-decoration: none;
+        user-select: none;
+
+        &:hover {
+            background-color: #b31234;
+        }
+    "#, ac = ACCENT_COLOR, fc = FONT_COLOR);
+}
+
+async fn fetch_info(id: &str) -> Result<LinkInfo, RequestError> {
+    let response = reqwest::get(endpoint!("info/{}", id))
+        .await
+        .map_err(|error| RequestError::UnsuccessfulRequest { error })?;
+
+    let status = response.status();
+
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(RequestError::from_response(status.as_u16(), &text));
+    }
+
+    response
+        .json::<LinkInfo>()
+        .await
+        .map_err(|error| RequestError::UnsuccessfulRequest { error })
+}
+
+fn format_timestamp(millis: i64) -> String {
+    let Ok(date_time) = OffsetDateTime::from_unix_timestamp(millis / 1000) else {
+        return millis.to_string();
+    };
+
+    let date_time = match try_get_local_offset() {
+        Some(offset) => date_time.to_offset(offset),
+        None => date_time,
+    };
+
+    date_time
+        .format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second]"
+        ))
+        .unwrap_or_else(|_| millis.to_string())
+}
+
+pub enum LinkPreviewMessage {
+    Loaded(LinkInfo),
+    Failed,
+}
+
+#[derive(Default)]
+pub enum LinkPreviewState {
+    #[default]
+    Loading,
+    Loaded(LinkInfo),
+    Failed,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct LinkPreviewProps {
+    pub id: AttrValue,
+    pub manage_messages: Callback<IndexMessage>,
+}
+
+/// Shows where a shortened link leads to without following it.
+#[derive(Default)]
+pub struct LinkPreview {
+    state: LinkPreviewState,
+}
+
+impl Component for LinkPreview {
+    type Message = LinkPreviewMessage;
+    type Properties = LinkPreviewProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let id = ctx.props().id.clone();
+        let manage_messages = ctx.props().manage_messages.clone();
+
+        ctx.link().send_future(async move {
+            match fetch_info(&id).await {
+                Ok(info) => LinkPreviewMessage::Loaded(info),
+                Err(e) => {
+                    manage_messages.emit(IndexMessage::AddMessage(e.into()));
+                    LinkPreviewMessage::Failed
+                },
+            }
+        });
+
+        Self::default()
+    }
+
+    fn update(&mut self, _: &Context<Self>, msg: Self::Message) -> bool {
+        self.state = match msg {
+            LinkPreviewMessage::Loaded(info) => LinkPreviewState::Loaded(info),
+            LinkPreviewMessage::Failed => LinkPreviewState::Failed,
+        };
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let content = match &self.state {
+            LinkPreviewState::Loading => html! { <p>{ "Loading..." }</p> },
+            LinkPreviewState::Failed => html! {
+                <p>{ format!("The link '{}' doesn't exist or is expired.", ctx.props().id) }</p>
+            },
+            LinkPreviewState::Loaded(info) => {
+                let remaining_uses = info
+                    .remaining_uses
+                    .map_or_else(|| "Unlimited".to_string(), |uses| uses.to_string());
+                let remaining_lifetime = info.remaining_lifetime.map_or_else(
+                    || "Forever".to_string(),
+                    |millis| {
+                        Duration {
+                            seconds: millis / 1000,
+                        }
+                        .to_string()
+                    },
+                );
+
+                html! {
+                    <>
+                        <span class={ LABEL.as_classes() }>{ "Leads to" }</span>
+                        <div class={ VALUE.as_classes() }>{ info.redirect_to.clone() }</div>
+                        <span class={ LABEL.as_classes() }>{ "Created at" }</span>
+                        <div class={ VALUE.as_classes() }>{ format_timestamp(info.created_at) }</div>
+                        <span class={ LABEL.as_classes() }>{ "Remaining uses" }</span>
+                        <div class={ VALUE.as_classes() }>{ remaining_uses }</div>
+                        <span class={ LABEL.as_classes() }>{ "Valid for (dd:hh:mm:ss)" }</span>
+                        <div class={ VALUE.as_classes() }>{ remaining_lifetime }</div>
+                        <a class={ BUTTON.as_classes() } href={ info.short_url.clone() }>{ "Follow link" }</a>
+                    </>
+                }
+            },
+        };
+
+        html! {
+            <>
+                <h1 class={ HEADING.as_classes() }>{ "Link preview" }</h1>
+                { content }
+            </>
+        }
+    }
+}
@@ -9,6 +9,7 @@ pub mod expiration_input;
 pub mod footer;
 pub mod link_form;
 mod link_input;
+pub mod link_preview;
 pub mod message_box;
 pub mod toggle_input;
 
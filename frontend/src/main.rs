@@ -32,9 +32,6 @@ pub const INPUT_WIDTH: &str = "235px";
         - do not use the App component as as a middleman but talk directly to message box
         - write test-cases for ui (how?)
         - rerender on server config
-    TODO @flamion:
-        - backend error enum or similar to give more specific messages
-            - alternately display the backend error directly
 */
 fn main() {
     setup_tracing_subscriber();
@@ -1,3 +1,6 @@
+use std::fmt::{Display, Formatter};
+
+use serde::Deserialize;
 use thiserror::Error;
 use yew::AttrValue;
 
@@ -28,11 +31,71 @@ pub enum RequestError {
     #[error("Request to backend unsuccessful: {error}")]
     // TODO better name
     UnsuccessfulRequest { error: reqwest::Error },
-    // TODO make error more specific by documenting the backend error possibilities
-    #[error("Json malformed")]
-    Backend400,
+    #[error("{error}")]
+    Backend { error: BackendError },
     #[error("Id '{id}' already in use")]
     IdInUse { id: String },
+    #[error("The server responded unexpectedly with code {code}")]
+    UnexpectedResponse { code: u16 },
+}
+
+impl RequestError {
+    /// Turns the body of an unsuccessful response into an error.
+    pub fn from_response(code: u16, text: &str) -> Self {
+        match serde_json::from_str::<BackendError>(text) {
+            Ok(error) => Self::Backend { error },
+            Err(_) => Self::UnexpectedResponse { code },
+        }
+    }
+}
+
+/// Mirrors the error codes of the backend's json error responses.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendErrorCode {
+    LinkConflict,
+    LinkExceedsMaxLength,
+    CustomIdExceedsMaxLength,
+    ReservedId,
+    LinkEmpty,
+    InvalidLink,
+    DisallowedScheme,
+    DomainBlocked,
+    DomainNotAllowed,
+    RandomIdMaxRetriesExceeded,
+    ExpiredLinkProvided,
+    LinkNotFound,
+    ManagementTokenMissing,
+    InvalidManagementToken,
+    PasswordRequired,
+    InvalidPassword,
+    AdminTokenMissing,
+    InvalidAdminToken,
+    RateLimited,
+    JsonExceedsMaxSize,
+    MalformedJson,
+    InvalidQuery,
+    InvalidImport,
+    Internal,
+    #[serde(other)]
+    Unknown,
+}
+
+/// The json body the backend sends along with every error.
+#[derive(Deserialize, Debug, Clone)]
+pub struct BackendError {
+    pub code: BackendErrorCode,
+    pub message: String,
+    pub limit: Option<usize>,
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.limit {
+            Some(limit) => write!(f, "{} The limit is {}.", self.message, limit),
+            None => write!(f, "{}", self.message),
+        }
+    }
 }
 
 impl Into<Message> for RequestError {
@@ -11,7 +11,7 @@ use validated::{
     Validated,
     Validated::{Fail, Good},
 };
-use web_sys::HtmlInputElement;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
 
 use crate::{
     components::{expiration_input::ExpirationType, link_form::LinkFormRefs},
@@ -32,6 +32,10 @@ pub struct LinkConfig {
     // could be u32 if https://github.com/flamion/shorty/issues/51 is resolved
     #[serde(skip_serializing_if = "Option::is_none")]
     pub valid_for: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_type: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
 }
 
 impl LinkConfig {
@@ -56,6 +60,8 @@ impl LinkConfig {
         let mut id = Ok(None);
         let mut max_uses = Ok(None);
         let mut valid_for = Ok(None);
+        let mut redirect_type = None;
+        let mut password = None;
 
         if input.checked() {
             id = Self::parse_id(refs, server_config)
@@ -67,6 +73,8 @@ impl LinkConfig {
             valid_for = Self::parse_valid_for(refs)
                 .ok()
                 .map_err(|e| errors.extend(e.into_iter()));
+            redirect_type = Self::parse_redirect_type(refs);
+            password = Self::parse_password(refs);
         }
 
         if errors.is_empty() {
@@ -75,6 +83,8 @@ impl LinkConfig {
                 id: id.unwrap(),
                 max_uses: max_uses.unwrap(),
                 valid_for: valid_for.unwrap(),
+                redirect_type,
+                password,
             })
         } else {
             Fail(NEVec::from_vec(errors).unwrap())
@@ -176,6 +186,37 @@ impl LinkConfig {
         Good(Some(value))
     }
 
+    fn parse_redirect_type(refs: &LinkFormRefs) -> Option<u16> {
+        let select = refs
+            .redirect_type_input
+            .cast::<HtmlSelectElement>()
+            .expect(&format!(
+                "Expected {:?} to be an HtmlSelectElement",
+                refs.redirect_type_input
+            ));
+
+        // the empty option stands for the server default
+        select.value().parse::<u16>().ok()
+    }
+
+    fn parse_password(refs: &LinkFormRefs) -> Option<String> {
+        let input = refs
+            .password_input
+            .cast::<HtmlInputElement>()
+            .expect(&format!(
+                "Expected {:?} to be an HtmlInputElement",
+                refs.password_input
+            ));
+
+        let value = input.value();
+
+        if value.is_empty() {
+            return None;
+        }
+
+        Some(value)
+    }
+
     fn parse_valid_for(refs: &LinkFormRefs) -> Validated<Option<i64>, FormError> {
         let input = refs
             .expiration_type
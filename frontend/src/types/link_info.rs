@@ -0,0 +1,13 @@
+use serde::Deserialize;
+
+/// Information about a link as returned by the `/info` endpoint.
+/// Timestamps and durations are in milliseconds.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkInfo {
+    pub id: String,
+    pub short_url: String,
+    pub redirect_to: String,
+    pub created_at: i64,
+    pub remaining_uses: Option<i64>,
+    pub remaining_lifetime: Option<i64>,
+}
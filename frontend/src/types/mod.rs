@@ -5,6 +5,7 @@ use serde::Deserialize;
 pub mod duration;
 pub mod error;
 pub mod link_config;
+pub mod link_info;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct ServerConfig {
@@ -14,4 +15,5 @@ pub struct ServerConfig {
     pub max_custom_id_length: usize,
     pub default_max_uses: i64,
     pub default_valid_for: i64,
+    pub default_redirect_type: u16,
 }
@@ -41,6 +41,17 @@ pub fn origin() -> impl Deref<Target = String> {
         .unwrap()
 }
 
+/// Returns the id of the link to preview if the page was opened as `/{id}+`.
+pub fn preview_id() -> Option<AttrValue> {
+    // FIXME handle unwrap
+    let path = web_sys::window().unwrap().location().pathname().ok()?;
+
+    path.strip_prefix('/')?
+        .strip_suffix('+')
+        .filter(|id| !id.is_empty())
+        .map(|id| AttrValue::from(id.to_string()))
+}
+
 #[macro_export]
 macro_rules! endpoint {
     ($($arg:tt)*) => {{